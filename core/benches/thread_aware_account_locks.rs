@@ -0,0 +1,129 @@
+#![feature(test)]
+
+extern crate test;
+
+use {
+    rand::{Rng, SeedableRng},
+    rand_chacha::ChaChaRng,
+    solana_core::banking_stage::transaction_scheduler::{
+        ThreadAwareAccountLocks, ThreadId, ThreadSet,
+    },
+    solana_sdk::pubkey::Pubkey,
+    test::Bencher,
+};
+
+const NUM_ACCOUNTS: usize = 10_000;
+const NUM_TRANSACTIONS: usize = 1_024;
+const NUM_WRITE_ACCOUNTS: usize = 2;
+const NUM_READ_ACCOUNTS: usize = 2;
+/// Zipf exponent of the account distribution, so that a few hot accounts are
+/// locked by many transactions, as on mainnet.
+const ZIPF_EXPONENT: f64 = 1.0;
+
+struct TransactionAccounts {
+    write_accounts: Vec<Pubkey>,
+    read_accounts: Vec<Pubkey>,
+}
+
+/// Samples account indexes in `0..num_accounts` with probability proportional
+/// to `1 / (index + 1)^exponent`.
+struct ZipfSampler {
+    cumulative_weights: Vec<f64>,
+}
+
+impl ZipfSampler {
+    fn new(num_accounts: usize, exponent: f64) -> Self {
+        let cumulative_weights = (1..=num_accounts)
+            .scan(0.0, |total, rank| {
+                *total += 1.0 / (rank as f64).powf(exponent);
+                Some(*total)
+            })
+            .collect();
+        Self { cumulative_weights }
+    }
+
+    fn sample(&self, rng: &mut impl Rng) -> usize {
+        let total = *self.cumulative_weights.last().unwrap();
+        let target = rng.gen::<f64>() * total;
+        self.cumulative_weights
+            .partition_point(|weight| *weight < target)
+            .min(self.cumulative_weights.len() - 1)
+    }
+}
+
+fn generate_transactions() -> Vec<TransactionAccounts> {
+    let mut rng = ChaChaRng::seed_from_u64(0);
+    let accounts: Vec<_> = (0..NUM_ACCOUNTS).map(|_| Pubkey::new_unique()).collect();
+    let sampler = ZipfSampler::new(NUM_ACCOUNTS, ZIPF_EXPONENT);
+
+    (0..NUM_TRANSACTIONS)
+        .map(|_| {
+            let mut indexes = Vec::with_capacity(NUM_WRITE_ACCOUNTS + NUM_READ_ACCOUNTS);
+            while indexes.len() < NUM_WRITE_ACCOUNTS + NUM_READ_ACCOUNTS {
+                let index = sampler.sample(&mut rng);
+                if !indexes.contains(&index) {
+                    indexes.push(index);
+                }
+            }
+            let (write_indexes, read_indexes) = indexes.split_at(NUM_WRITE_ACCOUNTS);
+            TransactionAccounts {
+                write_accounts: write_indexes.iter().map(|index| accounts[*index]).collect(),
+                read_accounts: read_indexes.iter().map(|index| accounts[*index]).collect(),
+            }
+        })
+        .collect()
+}
+
+/// Attempts to lock every transaction, selecting schedulable threads in a
+/// round-robin fashion, then releases all taken locks. Each iteration makes
+/// `NUM_TRANSACTIONS` lock attempts.
+fn bench_try_lock_accounts(bencher: &mut Bencher, num_threads: usize) {
+    let transactions = generate_transactions();
+    let mut locks = ThreadAwareAccountLocks::new(num_threads);
+    let mut locked = Vec::with_capacity(NUM_TRANSACTIONS);
+    let mut next_thread: ThreadId = 0;
+
+    bencher.iter(|| {
+        for transaction in &transactions {
+            let thread_id = locks.try_lock_accounts(
+                transaction.write_accounts.iter(),
+                transaction.read_accounts.iter(),
+                ThreadSet::any(num_threads),
+                |thread_set| {
+                    let thread_id = thread_set
+                        .contained_threads_iter()
+                        .find(|thread_id| *thread_id >= next_thread)
+                        .or_else(|| thread_set.contained_threads_iter().next())
+                        .unwrap();
+                    next_thread = (thread_id + 1) % num_threads;
+                    thread_id
+                },
+            );
+            if let Some(thread_id) = thread_id {
+                locked.push((transaction, thread_id));
+            }
+        }
+        for (transaction, thread_id) in locked.drain(..) {
+            locks.unlock_accounts(
+                transaction.write_accounts.iter(),
+                transaction.read_accounts.iter(),
+                thread_id,
+            );
+        }
+    });
+}
+
+#[bench]
+fn bench_try_lock_accounts_4_threads(bencher: &mut Bencher) {
+    bench_try_lock_accounts(bencher, 4);
+}
+
+#[bench]
+fn bench_try_lock_accounts_16_threads(bencher: &mut Bencher) {
+    bench_try_lock_accounts(bencher, 16);
+}
+
+#[bench]
+fn bench_try_lock_accounts_64_threads(bencher: &mut Bencher) {
+    bench_try_lock_accounts(bencher, 64);
+}
@@ -62,6 +62,9 @@ mod packet_receiver;
 mod read_write_account_set;
 #[allow(dead_code)]
 mod scheduler_messages;
+#[cfg(feature = "dev-context-only-utils")]
+pub mod transaction_scheduler;
+#[cfg(not(feature = "dev-context-only-utils"))]
 mod transaction_scheduler;
 
 // Fixed thread size seems to be fastest on GCP setup
@@ -438,7 +438,11 @@ impl Consumer {
         pre_results: impl Iterator<Item = Result<(), TransactionError>>,
     ) -> ProcessTransactionBatchOutput {
         let (
-            (transaction_qos_cost_results, cost_model_throttled_transactions_count),
+            (
+                transaction_qos_cost_results,
+                cost_reservation,
+                cost_model_throttled_transactions_count,
+            ),
             cost_model_us,
         ) = measure_us!(self.qos_service.select_and_accumulate_transaction_costs(
             bank,
@@ -473,27 +477,28 @@ impl Consumer {
             ..
         } = execute_and_commit_transactions_output;
 
-        // Costs of all transactions are added to the cost_tracker before processing.
-        // To ensure accurate tracking of compute units, transactions that ultimately
-        // were not included in the block should have their cost removed.
-        QosService::remove_costs(
-            transaction_qos_cost_results.iter(),
-            commit_transactions_result.as_ref().ok(),
-            bank,
-        );
-
-        // once feature `apply_cost_tracker_during_replay` is activated, leader shall no longer
-        // adjust block with executed cost (a behavior more inline with bankless leader), it
-        // should use requested, or default `compute_unit_limit` as transaction's execution cost.
-        if !bank
-            .feature_set
-            .is_active(&feature_set::apply_cost_tracker_during_replay::id())
-        {
-            QosService::update_costs(
-                transaction_qos_cost_results.iter(),
-                commit_transactions_result.as_ref().ok(),
-                bank,
-            );
+        // Costs of all transactions are reserved in the cost_tracker before processing.
+        // To ensure accurate tracking of compute units, the whole reservation is rolled back
+        // if the batch was not recorded, and transactions that ultimately were not included
+        // in the block have their cost removed.
+        match commit_transactions_result {
+            Ok(transaction_committed_status) => {
+                // once feature `apply_cost_tracker_during_replay` is activated, leader shall no
+                // longer adjust block with executed cost (a behavior more inline with bankless
+                // leader), it should use requested, or default `compute_unit_limit` as
+                // transaction's execution cost.
+                let update_execution_costs = !bank
+                    .feature_set
+                    .is_active(&feature_set::apply_cost_tracker_during_replay::id());
+                QosService::commit_costs(
+                    cost_reservation,
+                    transaction_qos_cost_results.iter(),
+                    transaction_committed_status,
+                    update_execution_costs,
+                    bank,
+                );
+            }
+            Err(_) => QosService::rollback_costs(cost_reservation, bank),
         }
 
         retryable_transaction_indexes
@@ -1,7 +1,8 @@
 use {
     super::immutable_deserialized_packet::ImmutableDeserializedPacket,
     solana_cost_model::{
-        block_cost_limits,
+        block_cost_limits::BlockCostLimits,
+        builtin_costs::BuiltinCostTable,
         cost_model::CostModel,
         cost_tracker::{CostTracker, CostTrackerError},
     },
@@ -34,22 +35,18 @@ pub struct ForwardBatch {
 impl Default for ForwardBatch {
     /// default ForwardBatch has cost_tracker with default limits
     fn default() -> Self {
-        Self::new(1)
+        Self::new(&BlockCostLimits::default(), 1)
     }
 }
 
 impl ForwardBatch {
     /// `ForwardBatch` keeps forwardable packets in a vector in its original fee prioritized order,
     /// Number of packets are limited by `cost_tracker` with customized `limit_ratio` to lower
-    /// (when `limit_ratio` > 1) `cost_tracker`'s default limits.
+    /// (when `limit_ratio` > 1) the given `block_cost_limits`.
     /// Lower limits yield smaller batch for forwarding.
-    fn new(limit_ratio: u32) -> Self {
+    fn new(block_cost_limits: &BlockCostLimits, limit_ratio: u32) -> Self {
         let mut cost_tracker = CostTracker::default();
-        cost_tracker.set_limits(
-            block_cost_limits::MAX_WRITABLE_ACCOUNT_UNITS.saturating_div(limit_ratio as u64),
-            block_cost_limits::MAX_BLOCK_UNITS.saturating_div(limit_ratio as u64),
-            block_cost_limits::MAX_VOTE_UNITS.saturating_div(limit_ratio as u64),
-        );
+        cost_tracker.set_block_cost_limits(block_cost_limits.divided_by(limit_ratio as u64));
         Self {
             cost_tracker,
             forwardable_packets: Vec::default(),
@@ -61,8 +58,13 @@ impl ForwardBatch {
         sanitized_transaction: &SanitizedTransaction,
         immutable_packet: Arc<ImmutableDeserializedPacket>,
         feature_set: &FeatureSet,
+        builtin_cost_table: &BuiltinCostTable,
     ) -> Result<u64, CostTrackerError> {
-        let tx_cost = CostModel::calculate_cost(sanitized_transaction, feature_set);
+        let tx_cost = CostModel::calculate_cost_with_builtin_cost_table(
+            sanitized_transaction,
+            feature_set,
+            builtin_cost_table,
+        );
         let res = self.cost_tracker.try_add(&tx_cost);
         if res.is_ok() {
             self.forwardable_packets.push(immutable_packet);
@@ -99,12 +101,26 @@ pub struct ForwardPacketBatchesByAccounts {
 
 impl ForwardPacketBatchesByAccounts {
     pub fn new_with_default_batch_limits() -> Self {
-        Self::new(FORWARDED_BLOCK_COMPUTE_RATIO, DEFAULT_NUMBER_OF_BATCHES)
+        Self::new_with_block_cost_limits(&BlockCostLimits::default())
+    }
+
+    /// Batch limits are derived from `block_cost_limits`, e.g. those of the
+    /// bank the packets are forwarded from.
+    pub fn new_with_block_cost_limits(block_cost_limits: &BlockCostLimits) -> Self {
+        Self::new(
+            block_cost_limits,
+            FORWARDED_BLOCK_COMPUTE_RATIO,
+            DEFAULT_NUMBER_OF_BATCHES,
+        )
     }
 
-    pub fn new(limit_ratio: u32, number_of_batches: u32) -> Self {
+    pub fn new(
+        block_cost_limits: &BlockCostLimits,
+        limit_ratio: u32,
+        number_of_batches: u32,
+    ) -> Self {
         let forward_batches = (0..number_of_batches)
-            .map(|_| ForwardBatch::new(limit_ratio))
+            .map(|_| ForwardBatch::new(block_cost_limits, limit_ratio))
             .collect();
         Self { forward_batches }
     }
@@ -115,10 +131,16 @@ impl ForwardPacketBatchesByAccounts {
         sanitized_transaction: &SanitizedTransaction,
         immutable_packet: Arc<ImmutableDeserializedPacket>,
         feature_set: &FeatureSet,
+        builtin_cost_table: &BuiltinCostTable,
     ) -> bool {
         for forward_batch in self.forward_batches.iter_mut() {
             if forward_batch
-                .try_add(sanitized_transaction, immutable_packet.clone(), feature_set)
+                .try_add(
+                    sanitized_transaction,
+                    immutable_packet.clone(),
+                    feature_set,
+                    builtin_cost_table,
+                )
                 .is_ok()
             {
                 return true;
@@ -167,7 +189,7 @@ mod tests {
 
         // set limit ratio so each batch can only have one test transaction
         let limit_ratio: u32 =
-            ((block_cost_limits::MAX_WRITABLE_ACCOUNT_UNITS - cost + 1) / cost) as u32;
+            ((BlockCostLimits::default().account_cost_limit - cost + 1) / cost) as u32;
         (sanitized_transaction, deserialized_packet, limit_ratio)
     }
 
@@ -175,7 +197,7 @@ mod tests {
     fn test_try_add_to_forward_batch() {
         let (tx, packet, limit_ratio) =
             build_test_transaction_and_packet(0u64, &Pubkey::new_unique());
-        let mut forward_batch = ForwardBatch::new(limit_ratio);
+        let mut forward_batch = ForwardBatch::new(&BlockCostLimits::default(), limit_ratio);
 
         // Assert first packet will be added to forwarding buffer
         assert!(forward_batch
@@ -183,6 +205,7 @@ mod tests {
                 &tx,
                 packet.immutable_section().clone(),
                 &FeatureSet::all_enabled(),
+                &BuiltinCostTable::default(),
             )
             .is_ok());
         assert_eq!(1, forward_batch.forwardable_packets.len());
@@ -194,6 +217,7 @@ mod tests {
                 &tx,
                 packet.immutable_section().clone(),
                 &FeatureSet::all_enabled(),
+                &BuiltinCostTable::default(),
             )
             .is_err());
         assert_eq!(1, forward_batch.forwardable_packets.len());
@@ -212,8 +236,11 @@ mod tests {
 
         // setup forwarding with 2 buckets, each only allow one transaction
         let number_of_batches = 2;
-        let mut forward_packet_batches_by_accounts =
-            ForwardPacketBatchesByAccounts::new(limit_ratio, number_of_batches);
+        let mut forward_packet_batches_by_accounts = ForwardPacketBatchesByAccounts::new(
+            &BlockCostLimits::default(),
+            limit_ratio,
+            number_of_batches,
+        );
 
         // Assert initially both batches are empty
         {
@@ -229,6 +256,7 @@ mod tests {
                 &tx_high_priority,
                 packet_high_priority.immutable_section().clone(),
                 &FeatureSet::all_enabled(),
+                &BuiltinCostTable::default(),
             ));
             let mut batches = forward_packet_batches_by_accounts.iter_batches();
             assert_eq!(1, batches.next().unwrap().len());
@@ -243,6 +271,7 @@ mod tests {
                 &tx_high_priority,
                 packet_high_priority.immutable_section().clone(),
                 &FeatureSet::all_enabled(),
+                &BuiltinCostTable::default(),
             ));
             let mut batches = forward_packet_batches_by_accounts.iter_batches();
             assert_eq!(1, batches.next().unwrap().len());
@@ -256,6 +285,7 @@ mod tests {
                 &tx_high_priority,
                 packet_high_priority.immutable_section().clone(),
                 &FeatureSet::all_enabled(),
+                &BuiltinCostTable::default(),
             ));
             let mut batches = forward_packet_batches_by_accounts.iter_batches();
             assert_eq!(1, batches.next().unwrap().len());
@@ -270,6 +300,7 @@ mod tests {
                 &tx_low_priority,
                 packet_low_priority.immutable_section().clone(),
                 &FeatureSet::all_enabled(),
+                &BuiltinCostTable::default(),
             ));
             let mut batches = forward_packet_batches_by_accounts.iter_batches();
             assert_eq!(2, batches.next().unwrap().len());
@@ -283,8 +314,11 @@ mod tests {
         let (tx, packet, limit_ratio) =
             build_test_transaction_and_packet(10, &solana_sdk::pubkey::new_rand());
         let number_of_batches = 1;
-        let mut forward_packet_batches_by_accounts =
-            ForwardPacketBatchesByAccounts::new(limit_ratio, number_of_batches);
+        let mut forward_packet_batches_by_accounts = ForwardPacketBatchesByAccounts::new(
+            &BlockCostLimits::default(),
+            limit_ratio,
+            number_of_batches,
+        );
 
         // Assert initially batch is empty, and accepting new packets
         {
@@ -61,12 +61,15 @@ impl Forwarder {
     ) {
         let forward_option = unprocessed_transaction_storage.forward_option();
 
-        // get current root bank from bank_forks, use it to sanitize transaction and
-        // load all accounts from address loader;
-        let current_bank = self.bank_forks.read().unwrap().root_bank();
+        // get current working bank from bank_forks, use it to sanitize transaction,
+        // load all accounts from address loader and limit forward batches by its
+        // block cost limits;
+        let current_bank = self.bank_forks.read().unwrap().working_bank();
 
         let mut forward_packet_batches_by_accounts =
-            ForwardPacketBatchesByAccounts::new_with_default_batch_limits();
+            ForwardPacketBatchesByAccounts::new_with_block_cost_limits(
+                &current_bank.block_cost_limits(),
+            );
 
         // sanitize and filter packets that are no longer valid (could be too old, a duplicate of something
         // already processed), then add to forwarding buffer.
@@ -160,7 +163,7 @@ impl Forwarder {
 
         self.update_data_budget();
         let packet_vec: Vec<_> = forwardable_packets
-            .filter(|p| !p.meta().forwarded())
+            .filter(|p| !p.meta().forwarded() && !p.meta().non_forwardable())
             .filter(|p| self.data_budget.take(p.meta().size))
             .filter_map(|p| p.data(..).map(|data| data.to_vec()))
             .collect();
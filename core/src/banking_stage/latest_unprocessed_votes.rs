@@ -289,6 +289,7 @@ impl LatestUnprocessedVotes {
                                 &sanitized_vote_transaction,
                                 deserialized_vote_packet,
                                 &bank.feature_set,
+                                bank.builtin_cost_table(),
                             ) {
                                 vote.forwarded = true;
                             } else {
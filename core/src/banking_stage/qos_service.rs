@@ -6,7 +6,12 @@
 use {
     super::{committer::CommitTransactionDetails, BatchedTransactionDetails},
     crossbeam_channel::{unbounded, Receiver, Sender},
-    solana_cost_model::{cost_model::CostModel, transaction_cost::TransactionCost},
+    solana_cost_model::{
+        builtin_costs::BuiltinCostTable,
+        cost_model::CostModel,
+        cost_tracker::{CostReservation, ReservedTransactionOutcome},
+        transaction_cost::TransactionCost,
+    },
     solana_measure::measure::Measure,
     solana_runtime::bank::Bank,
     solana_sdk::{
@@ -83,22 +88,28 @@ impl QosService {
     }
 
     /// Calculate cost of transactions, if not already filtered out, determine which ones to
-    /// include in the slot, and accumulate costs in the cost tracker.
-    /// Returns a vector of results containing selected transaction costs, and the number of
-    /// transactions that were *NOT* selected.
+    /// include in the slot, and reserve their costs in the cost tracker.
+    /// Returns a vector of results containing selected transaction costs, the reservation of
+    /// their costs, to be committed with `commit_costs` or rolled back with `rollback_costs`,
+    /// and the number of transactions that were *NOT* selected.
     pub fn select_and_accumulate_transaction_costs(
         &self,
         bank: &Bank,
         transactions: &[SanitizedTransaction],
         pre_results: impl Iterator<Item = transaction::Result<()>>,
-    ) -> (Vec<transaction::Result<TransactionCost>>, usize) {
-        let transaction_costs =
-            self.compute_transaction_costs(&bank.feature_set, transactions.iter(), pre_results);
-        let (transactions_qos_cost_results, num_included) = self.select_transactions_per_cost(
+    ) -> (
+        Vec<transaction::Result<TransactionCost>>,
+        CostReservation,
+        usize,
+    ) {
+        let transaction_costs = self.compute_transaction_costs(
+            &bank.feature_set,
+            bank.builtin_cost_table(),
             transactions.iter(),
-            transaction_costs.into_iter(),
-            bank,
+            pre_results,
         );
+        let (transactions_qos_cost_results, cost_reservation, num_included) = self
+            .select_transactions_per_cost(transactions.iter(), transaction_costs.into_iter(), bank);
         self.accumulate_estimated_transaction_costs(&Self::accumulate_batched_transaction_costs(
             transactions_qos_cost_results.iter(),
         ));
@@ -107,6 +118,7 @@ impl QosService {
 
         (
             transactions_qos_cost_results,
+            cost_reservation,
             cost_model_throttled_transactions_count,
         )
     }
@@ -116,13 +128,22 @@ impl QosService {
     fn compute_transaction_costs<'a>(
         &self,
         feature_set: &FeatureSet,
+        builtin_cost_table: &BuiltinCostTable,
         transactions: impl Iterator<Item = &'a SanitizedTransaction>,
         pre_results: impl Iterator<Item = transaction::Result<()>>,
     ) -> Vec<transaction::Result<TransactionCost>> {
         let mut compute_cost_time = Measure::start("compute_cost_time");
         let txs_costs: Vec<_> = transactions
             .zip(pre_results)
-            .map(|(tx, pre_result)| pre_result.map(|()| CostModel::calculate_cost(tx, feature_set)))
+            .map(|(tx, pre_result)| {
+                pre_result.map(|()| {
+                    CostModel::calculate_cost_with_builtin_cost_table(
+                        tx,
+                        feature_set,
+                        builtin_cost_table,
+                    )
+                })
+            })
             .collect();
         compute_cost_time.stop();
         self.metrics
@@ -138,21 +159,27 @@ impl QosService {
 
     /// Given a list of transactions and their costs, this function returns a corresponding
     /// list of Results that indicate if a transaction is selected to be included in the current block,
+    /// the reservation of the selected transactions' costs,
     /// and a count of the number of transactions that would fit in the block
     fn select_transactions_per_cost<'a>(
         &self,
         transactions: impl Iterator<Item = &'a SanitizedTransaction>,
         transactions_costs: impl Iterator<Item = transaction::Result<TransactionCost>>,
         bank: &Bank,
-    ) -> (Vec<transaction::Result<TransactionCost>>, usize) {
+    ) -> (
+        Vec<transaction::Result<TransactionCost>>,
+        CostReservation,
+        usize,
+    ) {
         let mut cost_tracking_time = Measure::start("cost_tracking_time");
         let mut cost_tracker = bank.write_cost_tracker().unwrap();
+        let mut cost_reservation = CostReservation::default();
         let mut num_included = 0;
         let select_results = transactions.zip(transactions_costs)
             .map(|(tx, cost)| {
                 match cost {
                     Ok(cost) => {
-                        match cost_tracker.try_add(&cost) {
+                        match cost_tracker.try_reserve(&mut cost_reservation, &cost) {
                             Ok(current_block_cost) => {
                                 debug!("slot {:?}, transaction {:?}, cost {:?}, fit into current block, current block cost {}", bank.slot(), tx, cost, current_block_cost);
                                 self.metrics.stats.selected_txs_count.fetch_add(1, Ordering::Relaxed);
@@ -175,93 +202,48 @@ impl QosService {
             .stats
             .cost_tracking_time
             .fetch_add(cost_tracking_time.as_us(), Ordering::Relaxed);
-        (select_results, num_included)
-    }
-
-    /// Updates the transaction costs for committed transactions. Does not handle removing costs
-    /// for transactions that didn't get recorded or committed
-    pub fn update_costs<'a>(
-        transaction_cost_results: impl Iterator<Item = &'a transaction::Result<TransactionCost>>,
-        transaction_committed_status: Option<&Vec<CommitTransactionDetails>>,
-        bank: &Arc<Bank>,
-    ) {
-        if let Some(transaction_committed_status) = transaction_committed_status {
-            Self::update_committed_transaction_costs(
-                transaction_cost_results,
-                transaction_committed_status,
-                bank,
-            )
-        }
+        (select_results, cost_reservation, num_included)
     }
 
-    /// Removes transaction costs from the cost tracker if not committed or recorded
-    pub fn remove_costs<'a>(
+    /// Commits the costs reserved for a batch that was recorded. Costs of transactions that
+    /// were not committed are removed. If `update_execution_costs` is set, committed
+    /// transactions are adjusted from their estimated to their actual execution units.
+    pub fn commit_costs<'a>(
+        cost_reservation: CostReservation,
         transaction_cost_results: impl Iterator<Item = &'a transaction::Result<TransactionCost>>,
-        transaction_committed_status: Option<&Vec<CommitTransactionDetails>>,
+        transaction_committed_status: &[CommitTransactionDetails],
+        update_execution_costs: bool,
         bank: &Arc<Bank>,
     ) {
-        match transaction_committed_status {
-            Some(transaction_committed_status) => Self::remove_uncommitted_transaction_costs(
-                transaction_cost_results,
-                transaction_committed_status,
-                bank,
-            ),
-            None => Self::remove_transaction_costs(transaction_cost_results, bank),
-        }
-    }
-
-    fn remove_uncommitted_transaction_costs<'a>(
-        transaction_cost_results: impl Iterator<Item = &'a transaction::Result<TransactionCost>>,
-        transaction_committed_status: &Vec<CommitTransactionDetails>,
-        bank: &Arc<Bank>,
-    ) {
-        let mut cost_tracker = bank.write_cost_tracker().unwrap();
-        transaction_cost_results
+        let outcomes = transaction_cost_results
             .zip(transaction_committed_status)
-            .for_each(|(tx_cost, transaction_committed_details)| {
-                // Only transactions that the qos service included have to be
-                // checked for update
-                if let Ok(tx_cost) = tx_cost {
-                    if *transaction_committed_details == CommitTransactionDetails::NotCommitted {
-                        cost_tracker.remove(tx_cost)
+            // Only transactions that the qos service included have reserved costs
+            .filter_map(|(tx_cost, transaction_committed_details)| {
+                let tx_cost = tx_cost.as_ref().ok()?;
+                let outcome = match transaction_committed_details {
+                    CommitTransactionDetails::NotCommitted => {
+                        ReservedTransactionOutcome::NotCommitted
                     }
-                }
-            });
-    }
-
-    fn update_committed_transaction_costs<'a>(
-        transaction_cost_results: impl Iterator<Item = &'a transaction::Result<TransactionCost>>,
-        transaction_committed_status: &Vec<CommitTransactionDetails>,
-        bank: &Arc<Bank>,
-    ) {
-        let mut cost_tracker = bank.write_cost_tracker().unwrap();
-        transaction_cost_results
-            .zip(transaction_committed_status)
-            .for_each(|(tx_cost, transaction_committed_details)| {
-                // Only transactions that the qos service included have to be
-                // checked for update
-                if let Ok(tx_cost) = tx_cost {
-                    if let CommitTransactionDetails::Committed { compute_units } =
-                        transaction_committed_details
-                    {
-                        cost_tracker.update_execution_cost(tx_cost, *compute_units)
+                    CommitTransactionDetails::Committed { compute_units } => {
+                        if update_execution_costs {
+                            ReservedTransactionOutcome::CommittedWithExecutionUnits(*compute_units)
+                        } else {
+                            ReservedTransactionOutcome::Committed
+                        }
                     }
-                }
+                };
+                Some((tx_cost, outcome))
             });
+        bank.write_cost_tracker()
+            .unwrap()
+            .commit_reservation(cost_reservation, outcomes);
     }
 
-    fn remove_transaction_costs<'a>(
-        transaction_cost_results: impl Iterator<Item = &'a transaction::Result<TransactionCost>>,
-        bank: &Arc<Bank>,
-    ) {
-        let mut cost_tracker = bank.write_cost_tracker().unwrap();
-        transaction_cost_results.for_each(|tx_cost| {
-            // Only transactions that the qos service included have to be
-            // removed
-            if let Ok(tx_cost) = tx_cost {
-                cost_tracker.remove(tx_cost);
-            }
-        });
+    /// Rolls back the costs reserved for a batch that was not recorded.
+    pub fn rollback_costs(cost_reservation: CostReservation, bank: &Arc<Bank>) {
+        bank.write_cost_tracker()
+            .unwrap()
+            .rollback_reservation(cost_reservation);
     }
 
     // metrics are reported by bank slot
@@ -691,6 +673,7 @@ mod tests {
         let qos_service = QosService::new(1);
         let txs_costs = qos_service.compute_transaction_costs(
             &FeatureSet::all_enabled(),
+            &BuiltinCostTable::default(),
             txs.iter(),
             std::iter::repeat(Ok(())),
         );
@@ -740,6 +723,7 @@ mod tests {
         let qos_service = QosService::new(1);
         let txs_costs = qos_service.compute_transaction_costs(
             &FeatureSet::all_enabled(),
+            &BuiltinCostTable::default(),
             txs.iter(),
             std::iter::repeat(Ok(())),
         );
@@ -749,7 +733,7 @@ mod tests {
         bank.write_cost_tracker()
             .unwrap()
             .set_limits(cost_limit, cost_limit, cost_limit);
-        let (results, num_selected) =
+        let (results, _cost_reservation, num_selected) =
             qos_service.select_transactions_per_cost(txs.iter(), txs_costs.into_iter(), &bank);
         assert_eq!(num_selected, 2);
 
@@ -784,6 +768,7 @@ mod tests {
             let qos_service = QosService::new(1);
             let txs_costs = qos_service.compute_transaction_costs(
                 &FeatureSet::all_enabled(),
+                &BuiltinCostTable::default(),
                 txs.iter(),
                 std::iter::repeat(Ok(())),
             );
@@ -791,7 +776,7 @@ mod tests {
                 .iter()
                 .map(|cost| cost.as_ref().unwrap().sum())
                 .sum();
-            let (qos_cost_results, _num_included) =
+            let (qos_cost_results, cost_reservation, _num_included) =
                 qos_service.select_transactions_per_cost(txs.iter(), txs_costs.into_iter(), &bank);
             assert_eq!(
                 total_txs_cost,
@@ -808,17 +793,13 @@ mod tests {
             let final_txs_cost = total_txs_cost + execute_units_adjustment * transaction_count;
 
             // All transactions are committed, no costs should be removed
-            QosService::remove_costs(qos_cost_results.iter(), Some(&commited_status), &bank);
-            assert_eq!(
-                total_txs_cost,
-                bank.read_cost_tracker().unwrap().block_cost()
+            QosService::commit_costs(
+                cost_reservation,
+                qos_cost_results.iter(),
+                &commited_status,
+                true,
+                &bank,
             );
-            assert_eq!(
-                transaction_count,
-                bank.read_cost_tracker().unwrap().transaction_count()
-            );
-
-            QosService::update_costs(qos_cost_results.iter(), Some(&commited_status), &bank);
             assert_eq!(
                 final_txs_cost,
                 bank.read_cost_tracker().unwrap().block_cost()
@@ -852,6 +833,7 @@ mod tests {
             let qos_service = QosService::new(1);
             let txs_costs = qos_service.compute_transaction_costs(
                 &FeatureSet::all_enabled(),
+                &BuiltinCostTable::default(),
                 txs.iter(),
                 std::iter::repeat(Ok(())),
             );
@@ -859,25 +841,19 @@ mod tests {
                 .iter()
                 .map(|cost| cost.as_ref().unwrap().sum())
                 .sum();
-            let (qos_cost_results, _num_included) =
+            let (_qos_cost_results, cost_reservation, _num_included) =
                 qos_service.select_transactions_per_cost(txs.iter(), txs_costs.into_iter(), &bank);
             assert_eq!(
                 total_txs_cost,
                 bank.read_cost_tracker().unwrap().block_cost()
             );
-
-            // update costs doesn't impact non-committed
-            QosService::update_costs(qos_cost_results.iter(), None, &bank);
-            assert_eq!(
-                total_txs_cost,
-                bank.read_cost_tracker().unwrap().block_cost()
-            );
             assert_eq!(
                 transaction_count,
                 bank.read_cost_tracker().unwrap().transaction_count()
             );
 
-            QosService::remove_costs(qos_cost_results.iter(), None, &bank);
+            // the batch was not recorded, so all of its reserved costs are rolled back
+            QosService::rollback_costs(cost_reservation, &bank);
             assert_eq!(0, bank.read_cost_tracker().unwrap().block_cost());
             assert_eq!(0, bank.read_cost_tracker().unwrap().transaction_count());
         }
@@ -906,6 +882,7 @@ mod tests {
             let qos_service = QosService::new(1);
             let txs_costs = qos_service.compute_transaction_costs(
                 &FeatureSet::all_enabled(),
+                &BuiltinCostTable::default(),
                 txs.iter(),
                 std::iter::repeat(Ok(())),
             );
@@ -913,7 +890,7 @@ mod tests {
                 .iter()
                 .map(|cost| cost.as_ref().unwrap().sum())
                 .sum();
-            let (qos_cost_results, _num_included) =
+            let (qos_cost_results, cost_reservation, _num_included) =
                 qos_service.select_transactions_per_cost(txs.iter(), txs_costs.into_iter(), &bank);
             assert_eq!(
                 total_txs_cost,
@@ -935,8 +912,13 @@ mod tests {
                 })
                 .collect();
 
-            QosService::remove_costs(qos_cost_results.iter(), Some(&commited_status), &bank);
-            QosService::update_costs(qos_cost_results.iter(), Some(&commited_status), &bank);
+            QosService::commit_costs(
+                cost_reservation,
+                qos_cost_results.iter(),
+                &commited_status,
+                true,
+                &bank,
+            );
 
             // assert the final block cost
             let mut expected_final_txs_count = 0u64;
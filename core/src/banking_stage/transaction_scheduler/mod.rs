@@ -1,2 +1,7 @@
 #[allow(dead_code)]
 mod thread_aware_account_locks;
+#[allow(dead_code)]
+mod thread_selector;
+
+#[cfg(feature = "dev-context-only-utils")]
+pub use thread_aware_account_locks::{ThreadAwareAccountLocks, ThreadBitSet, ThreadId, ThreadSet};
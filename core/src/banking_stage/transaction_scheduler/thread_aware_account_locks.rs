@@ -5,86 +5,478 @@ use {
         fmt::{Debug, Display},
         ops::{BitAnd, BitAndAssign, Sub},
     },
+    thiserror::Error,
 };
 
-pub(crate) const MAX_THREADS: usize = u64::BITS as usize;
+/// Number of threads tracked by each `u64` word of a `ThreadBitSet`.
+const THREADS_PER_WORD: usize = u64::BITS as usize;
+
+/// Maximum number of threads supported by the default-width `ThreadSet`.
+pub(crate) const MAX_THREADS: usize = ThreadSet::MAX_THREADS;
 
 /// Identifier for a thread
-pub(crate) type ThreadId = usize; // 0..MAX_THREADS-1
+pub type ThreadId = usize; // 0..MAX_THREADS-1
 
 type LockCount = u32;
 
+/// Errors from the fallible lock and unlock methods of `ThreadAwareAccountLocks`.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub(crate) enum LockError<const WORDS: usize = 1> {
+    #[error("thread_id must be < num_threads: {0}")]
+    InvalidThread(ThreadId),
+    #[error("outstanding write lock must be on same thread: {account} on {thread_id}")]
+    WriteLockedOnOtherThread {
+        account: Pubkey,
+        thread_id: ThreadId,
+    },
+    #[error("outstanding read lock must be on same thread: {account} on {thread_set}")]
+    ReadLockedOnOtherThread {
+        account: Pubkey,
+        thread_set: ThreadBitSet<WORDS>,
+    },
+    #[error("write lock must exist for account: {0}")]
+    WriteLockNotFound(Pubkey),
+    #[error("read lock must exist for account: {0}")]
+    ReadLockNotFound(Pubkey),
+    #[error("cannot unlock more write locks than are held for account: {0}")]
+    TooManyWriteUnlocks(Pubkey),
+    #[error("cannot unlock more read locks than are held for account: {0}")]
+    TooManyReadUnlocks(Pubkey),
+}
+
+/// Errors from `ThreadAwareAccountLocks::try_lock_transaction_batch`.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub(crate) enum BatchLockError {
+    #[error("batch must contain at least one transaction")]
+    EmptyBatch,
+    #[error("allowed threads must not be empty")]
+    NoAllowedThreads,
+    #[error("transaction {0} cannot be locked on a thread shared with the preceding transactions")]
+    Unschedulable(usize),
+}
+
 /// A bit-set of threads an account is scheduled or can be scheduled for.
+/// Each `u64` word holds 64 threads, so up to `64 * WORDS` threads are supported.
 #[derive(Copy, Clone, PartialEq, Eq)]
-pub(crate) struct ThreadSet(u64);
+pub struct ThreadBitSet<const WORDS: usize>([u64; WORDS]);
+
+/// Single-word `ThreadBitSet`, supporting up to 64 threads.
+pub type ThreadSet = ThreadBitSet<1>;
+
+/// Point-in-time summary of the state of `ThreadAwareAccountLocks`, used
+/// to diagnose scheduling stalls.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct LockTableSnapshot {
+    /// Write-locked accounts with the most outstanding write locks, sorted
+    /// by lock count in descending order.
+    pub(crate) hottest_write_locked_accounts: Vec<WriteLockedAccount>,
+    /// Number of outstanding write locks held by each thread.
+    pub(crate) write_lock_counts: Vec<u64>,
+    /// Number of outstanding read locks held by each thread.
+    pub(crate) read_lock_counts: Vec<u64>,
+    /// Number of accounts with an outstanding write lock.
+    pub(crate) num_write_locked_accounts: usize,
+    /// Number of accounts with an outstanding read lock.
+    pub(crate) num_read_locked_accounts: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct WriteLockedAccount {
+    pub(crate) account: Pubkey,
+    pub(crate) thread_id: ThreadId,
+    pub(crate) lock_count: LockCount,
+}
+
+impl LockTableSnapshot {
+    /// Reports the snapshot as a datapoint. Intended to be called by the
+    /// scheduler that owns the lock table, e.g. once per leader slot. Not
+    /// called yet, since no scheduler in banking stage owns a lock table.
+    pub(crate) fn report(&self) {
+        let (hottest_account, hottest_account_lock_count) = self
+            .hottest_write_locked_accounts
+            .first()
+            .map(|locked| (locked.account, locked.lock_count))
+            .unwrap_or_default();
+        datapoint_info!(
+            "banking_stage_scheduler_lock_table",
+            (
+                "num_write_locked_accounts",
+                self.num_write_locked_accounts,
+                i64
+            ),
+            (
+                "num_read_locked_accounts",
+                self.num_read_locked_accounts,
+                i64
+            ),
+            (
+                "max_thread_write_locks",
+                self.write_lock_counts
+                    .iter()
+                    .max()
+                    .copied()
+                    .unwrap_or_default(),
+                i64
+            ),
+            (
+                "max_thread_read_locks",
+                self.read_lock_counts
+                    .iter()
+                    .max()
+                    .copied()
+                    .unwrap_or_default(),
+                i64
+            ),
+            ("hottest_account", hottest_account.to_string(), String),
+            (
+                "hottest_account_lock_count",
+                hottest_account_lock_count,
+                i64
+            ),
+        );
+    }
+}
 
 struct AccountWriteLocks {
     thread_id: ThreadId,
     lock_count: LockCount,
 }
 
-struct AccountReadLocks {
-    thread_set: ThreadSet,
-    lock_counts: [LockCount; MAX_THREADS],
+struct AccountReadLocks<const WORDS: usize> {
+    thread_set: ThreadBitSet<WORDS>,
+    lock_counts: ReadLockCounts,
+}
+
+impl<const WORDS: usize> AccountReadLocks<WORDS> {
+    fn lock_count(&self, thread_id: ThreadId) -> LockCount {
+        self.lock_counts.get(thread_id)
+    }
+}
+
+/// Number of threads whose read lock counts are stored inline in
+/// `ReadLockCounts` before spilling to the heap.
+const INLINE_READ_LOCK_THREADS: usize = 4;
+
+/// Read lock counts for the threads holding read locks on an account.
+/// Accounts are usually read-locked by only a few threads at a time, so the
+/// counts of the first `INLINE_READ_LOCK_THREADS` threads are stored inline,
+/// and only additional threads require an allocation.
+struct ReadLockCounts {
+    num_inline: usize,
+    inline: [(ThreadId, LockCount); INLINE_READ_LOCK_THREADS],
+    spilled: Vec<(ThreadId, LockCount)>,
+}
+
+impl ReadLockCounts {
+    fn new() -> Self {
+        Self {
+            num_inline: 0,
+            inline: [(0, 0); INLINE_READ_LOCK_THREADS],
+            spilled: Vec::new(),
+        }
+    }
+
+    /// Returns the number of read locks held by `thread_id`.
+    fn get(&self, thread_id: ThreadId) -> LockCount {
+        self.inline[..self.num_inline]
+            .iter()
+            .chain(self.spilled.iter())
+            .find(|(lock_thread_id, _)| *lock_thread_id == thread_id)
+            .map(|(_, lock_count)| *lock_count)
+            .unwrap_or(0)
+    }
+
+    /// Adds a read lock for `thread_id`.
+    fn increment(&mut self, thread_id: ThreadId) {
+        let lock_count = self.inline[..self.num_inline]
+            .iter_mut()
+            .chain(self.spilled.iter_mut())
+            .find(|(lock_thread_id, _)| *lock_thread_id == thread_id)
+            .map(|(_, lock_count)| lock_count);
+        if let Some(lock_count) = lock_count {
+            *lock_count += 1;
+        } else if self.num_inline < INLINE_READ_LOCK_THREADS {
+            self.inline[self.num_inline] = (thread_id, 1);
+            self.num_inline += 1;
+        } else {
+            self.spilled.push((thread_id, 1));
+        }
+    }
+
+    /// Removes `count` read locks for `thread_id`, and returns the number of
+    /// read locks remaining. Panics if fewer than `count` locks are held.
+    fn decrement(&mut self, thread_id: ThreadId, count: LockCount) -> LockCount {
+        if let Some(index) = self.inline[..self.num_inline]
+            .iter()
+            .position(|(lock_thread_id, _)| *lock_thread_id == thread_id)
+        {
+            let lock_count = &mut self.inline[index].1;
+            *lock_count = lock_count
+                .checked_sub(count)
+                .expect("read lock count must be at least count");
+            let remaining = *lock_count;
+            if remaining == 0 {
+                // Fill the hole with the last inline entry, and refill the
+                // inline entries from the spilled entries.
+                self.num_inline -= 1;
+                self.inline[index] = self.inline[self.num_inline];
+                if let Some(spilled) = self.spilled.pop() {
+                    self.inline[self.num_inline] = spilled;
+                    self.num_inline += 1;
+                }
+            }
+            remaining
+        } else {
+            let index = self
+                .spilled
+                .iter()
+                .position(|(lock_thread_id, _)| *lock_thread_id == thread_id)
+                .expect("read lock count must exist for thread");
+            let lock_count = &mut self.spilled[index].1;
+            *lock_count = lock_count
+                .checked_sub(count)
+                .expect("read lock count must be at least count");
+            let remaining = *lock_count;
+            if remaining == 0 {
+                self.spilled.swap_remove(index);
+            }
+            remaining
+        }
+    }
 }
 
 /// Thread-aware account locks which allows for scheduling on threads
 /// that already hold locks on the account. This is useful for allowing
 /// queued transactions to be scheduled on a thread while the transaction
 /// is still being executed on the thread.
-pub(crate) struct ThreadAwareAccountLocks {
+/// Supports up to `64 * WORDS` threads.
+pub struct ThreadAwareAccountLocks<const WORDS: usize = 1> {
     /// Number of threads.
-    num_threads: usize, // 0..MAX_THREADS
+    num_threads: usize, // 0..ThreadBitSet::<WORDS>::MAX_THREADS
     /// Write locks - only one thread can hold a write lock at a time.
     /// Contains how many write locks are held by the thread.
     write_locks: HashMap<Pubkey, AccountWriteLocks>,
     /// Read locks - multiple threads can hold a read lock at a time.
     /// Contains thread-set for easily checking which threads are scheduled.
     /// Contains how many read locks are held by each thread.
-    read_locks: HashMap<Pubkey, AccountReadLocks>,
+    read_locks: HashMap<Pubkey, AccountReadLocks<WORDS>>,
+    /// Capacity `write_locks` is shrunk back to by `shrink_to_fit`.
+    write_locks_capacity: usize,
+    /// Capacity `read_locks` is shrunk back to by `shrink_to_fit`.
+    read_locks_capacity: usize,
 }
 
 impl ThreadAwareAccountLocks {
     /// Creates a new `ThreadAwareAccountLocks` with the given number of threads.
-    pub(crate) fn new(num_threads: usize) -> Self {
+    pub fn new(num_threads: usize) -> Self {
+        Self::new_with_words(num_threads)
+    }
+
+    /// Creates a new `ThreadAwareAccountLocks` with the given number of threads
+    /// and initial capacities for the write and read lock maps.
+    pub(crate) fn with_capacity(
+        num_threads: usize,
+        write_locks_capacity: usize,
+        read_locks_capacity: usize,
+    ) -> Self {
+        Self::with_capacity_and_words(num_threads, write_locks_capacity, read_locks_capacity)
+    }
+}
+
+impl<const WORDS: usize> ThreadAwareAccountLocks<WORDS> {
+    /// Creates a new `ThreadAwareAccountLocks` with the given number of threads,
+    /// using `ThreadBitSet`s of `WORDS` words.
+    pub(crate) fn new_with_words(num_threads: usize) -> Self {
+        Self::with_capacity_and_words(num_threads, 0, 0)
+    }
+
+    /// Creates a new `ThreadAwareAccountLocks` with the given number of threads
+    /// and initial capacities for the write and read lock maps, using
+    /// `ThreadBitSet`s of `WORDS` words.
+    pub(crate) fn with_capacity_and_words(
+        num_threads: usize,
+        write_locks_capacity: usize,
+        read_locks_capacity: usize,
+    ) -> Self {
+        let max_threads = ThreadBitSet::<WORDS>::MAX_THREADS;
         assert!(num_threads > 0, "num threads must be > 0");
         assert!(
-            num_threads <= MAX_THREADS,
-            "num threads must be <= {MAX_THREADS}"
+            num_threads <= max_threads,
+            "num threads must be <= {max_threads}"
         );
 
         Self {
             num_threads,
-            write_locks: HashMap::new(),
-            read_locks: HashMap::new(),
+            write_locks: HashMap::with_capacity(write_locks_capacity),
+            read_locks: HashMap::with_capacity(read_locks_capacity),
+            write_locks_capacity,
+            read_locks_capacity,
         }
     }
 
+    /// Releases excess capacity held by the lock maps, keeping at least the
+    /// initial capacities and room for all outstanding locks.
+    /// Intended to be called periodically, e.g. after a leader slot, so that a
+    /// burst of locked accounts does not keep the maps at peak size forever.
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.write_locks.shrink_to(self.write_locks_capacity);
+        self.read_locks.shrink_to(self.read_locks_capacity);
+    }
+
     /// Returns the `ThreadId` if the accounts are able to be locked
     /// for the given thread, otherwise `None` is returned.
     /// `allowed_threads` is a set of threads that the caller restricts locking to.
     /// If accounts are schedulable, then they are locked for the thread
     /// selected by the `thread_selector` function.
-    pub(crate) fn try_lock_accounts<'a>(
+    pub fn try_lock_accounts<'a>(
         &mut self,
         write_account_locks: impl Iterator<Item = &'a Pubkey> + Clone,
         read_account_locks: impl Iterator<Item = &'a Pubkey> + Clone,
-        allowed_threads: ThreadSet,
-        thread_selector: impl FnOnce(ThreadSet) -> ThreadId,
+        allowed_threads: ThreadBitSet<WORDS>,
+        thread_selector: impl FnOnce(ThreadBitSet<WORDS>) -> ThreadId,
     ) -> Option<ThreadId> {
-        let schedulable_threads = self.accounts_schedulable_threads(
+        self.try_lock_accounts_with_upgrades(
+            write_account_locks,
+            read_account_locks,
+            std::iter::empty(),
+            allowed_threads,
+            thread_selector,
+        )
+    }
+
+    /// Like `try_lock_accounts`, but also upgrades a read lock on each account
+    /// in `upgrade_account_locks` to a write lock on the selected thread.
+    /// The pending upgrades restrict the schedulable threads to the sole
+    /// read-lock holder of each of those accounts, see
+    /// `upgrade_schedulable_threads`. An account listed more than once is
+    /// upgraded once per occurrence, so the holder must hold at least that
+    /// many read locks on it.
+    /// Returns `None`, without taking any locks, if the upgrades cannot be
+    /// applied or if an upgraded account is also in the write or read locks.
+    pub(crate) fn try_lock_accounts_with_upgrades<'a>(
+        &mut self,
+        write_account_locks: impl Iterator<Item = &'a Pubkey> + Clone,
+        read_account_locks: impl Iterator<Item = &'a Pubkey> + Clone,
+        upgrade_account_locks: impl Iterator<Item = &'a Pubkey>,
+        allowed_threads: ThreadBitSet<WORDS>,
+        thread_selector: impl FnOnce(ThreadBitSet<WORDS>) -> ThreadId,
+    ) -> Option<ThreadId> {
+        let mut upgrades: Vec<(&Pubkey, LockCount)> = Vec::new();
+        for account in upgrade_account_locks {
+            match upgrades.iter_mut().find(|(upgrade, _)| *upgrade == account) {
+                Some((_, count)) => *count += 1,
+                None => upgrades.push((account, 1)),
+            }
+        }
+        let is_upgraded =
+            |account: &Pubkey| upgrades.iter().any(|(upgrade, _)| *upgrade == account);
+        if write_account_locks.clone().any(is_upgraded)
+            || read_account_locks.clone().any(is_upgraded)
+        {
+            return None;
+        }
+
+        let mut schedulable_threads = self.accounts_schedulable_threads(
             write_account_locks.clone(),
             read_account_locks.clone(),
         )? & allowed_threads;
-        (!schedulable_threads.is_empty()).then(|| {
-            let thread_id = thread_selector(schedulable_threads);
-            self.lock_accounts(write_account_locks, read_account_locks, thread_id);
-            thread_id
-        })
+        for (account, count) in &upgrades {
+            schedulable_threads &= self.upgrade_schedulable_threads(account);
+            // At most one thread remains schedulable once there is an upgrade.
+            let thread_id = schedulable_threads.only_one_contained()?;
+            if self.read_locks.get(account)?.lock_count(thread_id) < *count {
+                return None;
+            }
+        }
+        if schedulable_threads.is_empty() {
+            return None;
+        }
+
+        let thread_id = thread_selector(schedulable_threads);
+        for (account, count) in upgrades {
+            for _ in 0..count {
+                self.try_upgrade_read_lock(account, thread_id)
+                    .expect("upgrades are validated before selecting a thread");
+            }
+        }
+        self.lock_accounts(write_account_locks, read_account_locks, thread_id);
+        Some(thread_id)
+    }
+
+    /// Attempts to lock the accounts of every transaction in `batch_account_locks`
+    /// on a single thread. Each item holds the writable and readable accounts of
+    /// one transaction.
+    /// Returns the selected `ThreadId` if all transactions can be locked together.
+    /// Otherwise, no locks are taken and an error is returned. If the batch is
+    /// non-empty and some thread is allowed, the error holds the index of the
+    /// first transaction that cannot be locked on a thread shared with all
+    /// preceding transactions in the batch.
+    pub(crate) fn try_lock_transaction_batch<'a, W, R>(
+        &mut self,
+        batch_account_locks: &[(W, R)],
+        allowed_threads: ThreadBitSet<WORDS>,
+        thread_selector: impl FnOnce(ThreadBitSet<WORDS>) -> ThreadId,
+    ) -> Result<ThreadId, BatchLockError>
+    where
+        W: Iterator<Item = &'a Pubkey> + Clone,
+        R: Iterator<Item = &'a Pubkey> + Clone,
+    {
+        if batch_account_locks.is_empty() {
+            return Err(BatchLockError::EmptyBatch);
+        }
+        let mut schedulable_threads = ThreadBitSet::any(self.num_threads) & allowed_threads;
+        if schedulable_threads.is_empty() {
+            return Err(BatchLockError::NoAllowedThreads);
+        }
+
+        for (index, (write_account_locks, read_account_locks)) in
+            batch_account_locks.iter().enumerate()
+        {
+            schedulable_threads &= self
+                .accounts_schedulable_threads(
+                    write_account_locks.clone(),
+                    read_account_locks.clone(),
+                )
+                .unwrap_or_else(ThreadBitSet::none);
+            if schedulable_threads.is_empty() {
+                return Err(BatchLockError::Unschedulable(index));
+            }
+        }
+
+        let thread_id = thread_selector(schedulable_threads);
+        for (write_account_locks, read_account_locks) in batch_account_locks {
+            self.lock_accounts(
+                write_account_locks.clone(),
+                read_account_locks.clone(),
+                thread_id,
+            );
+        }
+        Ok(thread_id)
+    }
+
+    /// Unlocks the accounts of every transaction in `batch_account_locks` for
+    /// the given thread.
+    pub(crate) fn unlock_transaction_batch<'a, W, R>(
+        &mut self,
+        batch_account_locks: &[(W, R)],
+        thread_id: ThreadId,
+    ) where
+        W: Iterator<Item = &'a Pubkey> + Clone,
+        R: Iterator<Item = &'a Pubkey> + Clone,
+    {
+        self.unlock_accounts(
+            batch_account_locks
+                .iter()
+                .flat_map(|(write_account_locks, _)| write_account_locks.clone()),
+            batch_account_locks
+                .iter()
+                .flat_map(|(_, read_account_locks)| read_account_locks.clone()),
+            thread_id,
+        );
     }
 
     /// Unlocks the accounts for the given thread.
-    pub(crate) fn unlock_accounts<'a>(
+    pub fn unlock_accounts<'a>(
         &mut self,
         write_account_locks: impl Iterator<Item = &'a Pubkey>,
         read_account_locks: impl Iterator<Item = &'a Pubkey>,
@@ -99,13 +491,75 @@ impl ThreadAwareAccountLocks {
         }
     }
 
+    /// Unlocks the accounts for all transactions of a completed batch on the
+    /// given thread. Each item is an account and the number of transactions
+    /// in the batch that locked it, so each account's entry is only looked up
+    /// once no matter how many transactions in the batch locked it.
+    /// The caller is expected to have grouped the accounts already, e.g. while
+    /// building the batch.
+    pub(crate) fn unlock_batch<'a>(
+        &mut self,
+        write_account_locks: impl Iterator<Item = (&'a Pubkey, LockCount)>,
+        read_account_locks: impl Iterator<Item = (&'a Pubkey, LockCount)>,
+        thread_id: ThreadId,
+    ) {
+        for (account, count) in write_account_locks {
+            self.write_unlock_account_count(account, thread_id, count);
+        }
+
+        for (account, count) in read_account_locks {
+            self.read_unlock_account_count(account, thread_id, count);
+        }
+    }
+
+    /// Returns a snapshot of the current lock state, including up to
+    /// `num_hottest` of the write-locked accounts with the most outstanding
+    /// write locks.
+    pub(crate) fn snapshot(&self, num_hottest: usize) -> LockTableSnapshot {
+        let mut write_lock_counts = vec![0; self.num_threads];
+        let mut hottest_write_locked_accounts = Vec::with_capacity(self.write_locks.len());
+        for (account, write_locks) in &self.write_locks {
+            write_lock_counts[write_locks.thread_id] += u64::from(write_locks.lock_count);
+            hottest_write_locked_accounts.push(WriteLockedAccount {
+                account: *account,
+                thread_id: write_locks.thread_id,
+                lock_count: write_locks.lock_count,
+            });
+        }
+        let hotter = |a: &WriteLockedAccount, b: &WriteLockedAccount| {
+            b.lock_count
+                .cmp(&a.lock_count)
+                .then_with(|| a.account.cmp(&b.account))
+        };
+        if num_hottest < hottest_write_locked_accounts.len() {
+            hottest_write_locked_accounts.select_nth_unstable_by(num_hottest, hotter);
+            hottest_write_locked_accounts.truncate(num_hottest);
+        }
+        hottest_write_locked_accounts.sort_unstable_by(hotter);
+
+        let mut read_lock_counts = vec![0; self.num_threads];
+        for read_locks in self.read_locks.values() {
+            for thread_id in read_locks.thread_set.contained_threads_iter() {
+                read_lock_counts[thread_id] += u64::from(read_locks.lock_count(thread_id));
+            }
+        }
+
+        LockTableSnapshot {
+            hottest_write_locked_accounts,
+            write_lock_counts,
+            read_lock_counts,
+            num_write_locked_accounts: self.write_locks.len(),
+            num_read_locked_accounts: self.read_locks.len(),
+        }
+    }
+
     /// Returns `ThreadSet` that the given accounts can be scheduled on.
     fn accounts_schedulable_threads<'a>(
         &self,
         write_account_locks: impl Iterator<Item = &'a Pubkey>,
         read_account_locks: impl Iterator<Item = &'a Pubkey>,
-    ) -> Option<ThreadSet> {
-        let mut schedulable_threads = ThreadSet::any(self.num_threads);
+    ) -> Option<ThreadBitSet<WORDS>> {
+        let mut schedulable_threads = ThreadBitSet::any(self.num_threads);
 
         for account in write_account_locks {
             schedulable_threads &= self.write_schedulable_threads(account);
@@ -125,12 +579,12 @@ impl ThreadAwareAccountLocks {
     }
 
     /// Returns `ThreadSet` of schedulable threads for the given readable account.
-    fn read_schedulable_threads(&self, account: &Pubkey) -> ThreadSet {
+    fn read_schedulable_threads(&self, account: &Pubkey) -> ThreadBitSet<WORDS> {
         self.schedulable_threads::<false>(account)
     }
 
     /// Returns `ThreadSet` of schedulable threads for the given writable account.
-    fn write_schedulable_threads(&self, account: &Pubkey) -> ThreadSet {
+    fn write_schedulable_threads(&self, account: &Pubkey) -> ThreadBitSet<WORDS> {
         self.schedulable_threads::<true>(account)
     }
 
@@ -141,21 +595,21 @@ impl ThreadAwareAccountLocks {
     /// If only read-locked, the only write-schedulable thread is if a single thread
     ///   holds all read locks. Otherwise, no threads are write-schedulable.
     /// If only read-locked, all threads are read-schedulable.
-    fn schedulable_threads<const WRITE: bool>(&self, account: &Pubkey) -> ThreadSet {
+    fn schedulable_threads<const WRITE: bool>(&self, account: &Pubkey) -> ThreadBitSet<WORDS> {
         match (self.write_locks.get(account), self.read_locks.get(account)) {
-            (None, None) => ThreadSet::any(self.num_threads),
+            (None, None) => ThreadBitSet::any(self.num_threads),
             (None, Some(read_locks)) => {
                 if WRITE {
                     read_locks
                         .thread_set
                         .only_one_contained()
-                        .map(ThreadSet::only)
-                        .unwrap_or_else(ThreadSet::none)
+                        .map(ThreadBitSet::only)
+                        .unwrap_or_else(ThreadBitSet::none)
                 } else {
-                    ThreadSet::any(self.num_threads)
+                    ThreadBitSet::any(self.num_threads)
                 }
             }
-            (Some(write_locks), None) => ThreadSet::only(write_locks.thread_id),
+            (Some(write_locks), None) => ThreadBitSet::only(write_locks.thread_id),
             (Some(write_locks), Some(read_locks)) => {
                 assert_eq!(
                     read_locks.thread_set.only_one_contained(),
@@ -173,10 +627,8 @@ impl ThreadAwareAccountLocks {
         read_account_locks: impl Iterator<Item = &'a Pubkey>,
         thread_id: ThreadId,
     ) {
-        assert!(
-            thread_id < self.num_threads,
-            "thread_id must be < num_threads"
-        );
+        self.check_thread_id(thread_id)
+            .unwrap_or_else(|err| panic!("{err}"));
         for account in write_account_locks {
             self.write_lock_account(account, thread_id);
         }
@@ -187,18 +639,44 @@ impl ThreadAwareAccountLocks {
     }
 
     /// Locks the given `account` for writing on `thread_id`.
-    /// Panics if the account is already locked for writing on another thread.
+    /// Panics if the account is already locked on another thread.
     fn write_lock_account(&mut self, account: &Pubkey, thread_id: ThreadId) {
+        self.try_write_lock_account(account, thread_id)
+            .unwrap_or_else(|err| panic!("{err}"));
+    }
+
+    /// Locks the given `account` for writing on `thread_id`.
+    /// Returns an error, without modifying any locks, if the account is
+    /// already locked on another thread.
+    pub(crate) fn try_write_lock_account(
+        &mut self,
+        account: &Pubkey,
+        thread_id: ThreadId,
+    ) -> Result<(), LockError<WORDS>> {
+        self.check_thread_id(thread_id)?;
+
+        // Check for outstanding read-locks
+        if let Some(read_locks) = self.read_locks.get(account) {
+            if read_locks.thread_set != ThreadBitSet::only(thread_id) {
+                return Err(LockError::ReadLockedOnOtherThread {
+                    account: *account,
+                    thread_set: read_locks.thread_set,
+                });
+            }
+        }
+
         match self.write_locks.entry(*account) {
             Entry::Occupied(mut entry) => {
                 let AccountWriteLocks {
                     thread_id: lock_thread_id,
                     lock_count,
                 } = entry.get_mut();
-                assert_eq!(
-                    *lock_thread_id, thread_id,
-                    "outstanding write lock must be on same thread"
-                );
+                if *lock_thread_id != thread_id {
+                    return Err(LockError::WriteLockedOnOtherThread {
+                        account: *account,
+                        thread_id: *lock_thread_id,
+                    });
+                }
 
                 *lock_count += 1;
             }
@@ -210,161 +688,326 @@ impl ThreadAwareAccountLocks {
             }
         }
 
-        // Check for outstanding read-locks
-        if let Some(read_locks) = self.read_locks.get(account) {
-            assert_eq!(
-                read_locks.thread_set,
-                ThreadSet::only(thread_id),
-                "outstanding read lock must be on same thread"
-            );
-        }
+        Ok(())
     }
 
     /// Unlocks the given `account` for writing on `thread_id`.
     /// Panics if the account is not locked for writing on `thread_id`.
     fn write_unlock_account(&mut self, account: &Pubkey, thread_id: ThreadId) {
+        self.write_unlock_account_count(account, thread_id, 1);
+    }
+
+    /// Removes `count` write locks on the given `account` for `thread_id`.
+    /// Panics if the account is not locked for writing at least `count` times
+    /// on `thread_id`.
+    fn write_unlock_account_count(
+        &mut self,
+        account: &Pubkey,
+        thread_id: ThreadId,
+        count: LockCount,
+    ) {
+        self.try_write_unlock_account_count(account, thread_id, count)
+            .unwrap_or_else(|err| panic!("{err}"));
+    }
+
+    /// Unlocks the given `account` for writing on `thread_id`.
+    /// Returns an error, without modifying any locks, if the account is not
+    /// locked for writing on `thread_id`.
+    pub(crate) fn try_write_unlock_account(
+        &mut self,
+        account: &Pubkey,
+        thread_id: ThreadId,
+    ) -> Result<(), LockError<WORDS>> {
+        self.try_write_unlock_account_count(account, thread_id, 1)
+    }
+
+    /// Removes `count` write locks on the given `account` for `thread_id`.
+    /// Returns an error, without modifying any locks, if the account is not
+    /// locked for writing at least `count` times on `thread_id`.
+    fn try_write_unlock_account_count(
+        &mut self,
+        account: &Pubkey,
+        thread_id: ThreadId,
+        count: LockCount,
+    ) -> Result<(), LockError<WORDS>> {
         match self.write_locks.entry(*account) {
             Entry::Occupied(mut entry) => {
                 let AccountWriteLocks {
                     thread_id: lock_thread_id,
                     lock_count,
                 } = entry.get_mut();
-                assert_eq!(
-                    *lock_thread_id, thread_id,
-                    "outstanding write lock must be on same thread"
-                );
-                *lock_count -= 1;
+                if *lock_thread_id != thread_id {
+                    return Err(LockError::WriteLockedOnOtherThread {
+                        account: *account,
+                        thread_id: *lock_thread_id,
+                    });
+                }
+                if *lock_count < count {
+                    return Err(LockError::TooManyWriteUnlocks(*account));
+                }
+                *lock_count -= count;
                 if *lock_count == 0 {
                     entry.remove();
                 }
+                Ok(())
             }
-            Entry::Vacant(_) => {
-                panic!("write lock must exist for account: {account}");
-            }
+            Entry::Vacant(_) => Err(LockError::WriteLockNotFound(*account)),
         }
     }
 
     /// Locks the given `account` for reading on `thread_id`.
     /// Panics if the account is already locked for writing on another thread.
     fn read_lock_account(&mut self, account: &Pubkey, thread_id: ThreadId) {
+        self.try_read_lock_account(account, thread_id)
+            .unwrap_or_else(|err| panic!("{err}"));
+    }
+
+    /// Locks the given `account` for reading on `thread_id`.
+    /// Returns an error, without modifying any locks, if the account is
+    /// already locked for writing on another thread.
+    pub(crate) fn try_read_lock_account(
+        &mut self,
+        account: &Pubkey,
+        thread_id: ThreadId,
+    ) -> Result<(), LockError<WORDS>> {
+        self.check_thread_id(thread_id)?;
+
+        // Check for outstanding write-locks
+        if let Some(write_locks) = self.write_locks.get(account) {
+            if write_locks.thread_id != thread_id {
+                return Err(LockError::WriteLockedOnOtherThread {
+                    account: *account,
+                    thread_id: write_locks.thread_id,
+                });
+            }
+        }
+
         match self.read_locks.entry(*account) {
             Entry::Occupied(mut entry) => {
-                let AccountReadLocks {
-                    thread_set,
-                    lock_counts,
-                } = entry.get_mut();
-                thread_set.insert(thread_id);
-                lock_counts[thread_id] += 1;
+                let read_locks = entry.get_mut();
+                read_locks.thread_set.insert(thread_id);
+                read_locks.lock_counts.increment(thread_id);
             }
             Entry::Vacant(entry) => {
-                let mut lock_counts = [0; MAX_THREADS];
-                lock_counts[thread_id] = 1;
+                let mut lock_counts = ReadLockCounts::new();
+                lock_counts.increment(thread_id);
                 entry.insert(AccountReadLocks {
-                    thread_set: ThreadSet::only(thread_id),
+                    thread_set: ThreadBitSet::only(thread_id),
                     lock_counts,
                 });
             }
         }
 
-        // Check for outstanding write-locks
-        if let Some(write_locks) = self.write_locks.get(account) {
-            assert_eq!(
-                write_locks.thread_id, thread_id,
-                "outstanding write lock must be on same thread"
-            );
-        }
+        Ok(())
     }
 
     /// Unlocks the given `account` for reading on `thread_id`.
     /// Panics if the account is not locked for reading on `thread_id`.
     fn read_unlock_account(&mut self, account: &Pubkey, thread_id: ThreadId) {
+        self.read_unlock_account_count(account, thread_id, 1);
+    }
+
+    /// Removes `count` read locks on the given `account` for `thread_id`.
+    /// Panics if the account is not locked for reading at least `count` times
+    /// on `thread_id`.
+    fn read_unlock_account_count(
+        &mut self,
+        account: &Pubkey,
+        thread_id: ThreadId,
+        count: LockCount,
+    ) {
+        self.try_read_unlock_account_count(account, thread_id, count)
+            .unwrap_or_else(|err| panic!("{err}"));
+    }
+
+    /// Unlocks the given `account` for reading on `thread_id`.
+    /// Returns an error, without modifying any locks, if the account is not
+    /// locked for reading on `thread_id`.
+    pub(crate) fn try_read_unlock_account(
+        &mut self,
+        account: &Pubkey,
+        thread_id: ThreadId,
+    ) -> Result<(), LockError<WORDS>> {
+        self.try_read_unlock_account_count(account, thread_id, 1)
+    }
+
+    /// Removes `count` read locks on the given `account` for `thread_id`.
+    /// Returns an error, without modifying any locks, if the account is not
+    /// locked for reading at least `count` times on `thread_id`.
+    fn try_read_unlock_account_count(
+        &mut self,
+        account: &Pubkey,
+        thread_id: ThreadId,
+        count: LockCount,
+    ) -> Result<(), LockError<WORDS>> {
         match self.read_locks.entry(*account) {
             Entry::Occupied(mut entry) => {
-                let AccountReadLocks {
-                    thread_set,
-                    lock_counts,
-                } = entry.get_mut();
-                assert!(
-                    thread_set.contains(thread_id),
-                    "outstanding read lock must be on same thread"
-                );
-                lock_counts[thread_id] -= 1;
-                if lock_counts[thread_id] == 0 {
-                    thread_set.remove(thread_id);
-                    if thread_set.is_empty() {
+                let read_locks = entry.get_mut();
+                if !read_locks.thread_set.contains(thread_id) {
+                    return Err(LockError::ReadLockedOnOtherThread {
+                        account: *account,
+                        thread_set: read_locks.thread_set,
+                    });
+                }
+                if read_locks.lock_count(thread_id) < count {
+                    return Err(LockError::TooManyReadUnlocks(*account));
+                }
+                if read_locks.lock_counts.decrement(thread_id, count) == 0 {
+                    read_locks.thread_set.remove(thread_id);
+                    if read_locks.thread_set.is_empty() {
                         entry.remove();
                     }
                 }
+                Ok(())
             }
-            Entry::Vacant(_) => {
-                panic!("read lock must exist for account: {account}");
-            }
+            Entry::Vacant(_) => Err(LockError::ReadLockNotFound(*account)),
+        }
+    }
+
+    /// Returns `ThreadSet` of threads on which a read lock on the given
+    /// `account` can be upgraded to a write lock.
+    /// An upgrade is only possible if a single thread holds all read locks.
+    pub(crate) fn upgrade_schedulable_threads(&self, account: &Pubkey) -> ThreadBitSet<WORDS> {
+        self.read_locks
+            .get(account)
+            .and_then(|read_locks| read_locks.thread_set.only_one_contained())
+            .map(ThreadBitSet::only)
+            .unwrap_or_else(ThreadBitSet::none)
+    }
+
+    /// Upgrades one read lock on the given `account` held by `thread_id` to a
+    /// write lock on the same thread, without releasing the account in between.
+    /// Returns an error, without modifying any locks, if `thread_id` does not
+    /// hold a read lock on the account or if other threads also hold read locks.
+    pub(crate) fn try_upgrade_read_lock(
+        &mut self,
+        account: &Pubkey,
+        thread_id: ThreadId,
+    ) -> Result<(), LockError<WORDS>> {
+        self.check_thread_id(thread_id)?;
+
+        // Only the sole read-lock holder may upgrade. Any outstanding write
+        // lock must then also be held by `thread_id`.
+        let Some(read_locks) = self.read_locks.get(account) else {
+            return Err(LockError::ReadLockNotFound(*account));
+        };
+        if read_locks.thread_set != ThreadBitSet::only(thread_id) {
+            return Err(LockError::ReadLockedOnOtherThread {
+                account: *account,
+                thread_set: read_locks.thread_set,
+            });
+        }
+
+        self.try_read_unlock_account(account, thread_id)?;
+        self.try_write_lock_account(account, thread_id)
+    }
+
+    /// Returns an error if `thread_id` is not a valid thread.
+    fn check_thread_id(&self, thread_id: ThreadId) -> Result<(), LockError<WORDS>> {
+        if thread_id < self.num_threads {
+            Ok(())
+        } else {
+            Err(LockError::InvalidThread(thread_id))
         }
     }
 }
 
-impl BitAnd for ThreadSet {
+impl<const WORDS: usize> BitAnd for ThreadBitSet<WORDS> {
     type Output = Self;
 
-    fn bitand(self, rhs: Self) -> Self::Output {
-        Self(self.0 & rhs.0)
+    fn bitand(mut self, rhs: Self) -> Self::Output {
+        self &= rhs;
+        self
     }
 }
 
-impl BitAndAssign for ThreadSet {
+impl<const WORDS: usize> BitAndAssign for ThreadBitSet<WORDS> {
     fn bitand_assign(&mut self, rhs: Self) {
-        self.0 &= rhs.0;
+        for (word, rhs_word) in self.0.iter_mut().zip(rhs.0) {
+            *word &= rhs_word;
+        }
     }
 }
 
-impl Sub for ThreadSet {
+impl<const WORDS: usize> Sub for ThreadBitSet<WORDS> {
     type Output = Self;
 
-    fn sub(self, rhs: Self) -> Self::Output {
-        Self(self.0 & !rhs.0)
+    fn sub(mut self, rhs: Self) -> Self::Output {
+        for (word, rhs_word) in self.0.iter_mut().zip(rhs.0) {
+            *word &= !rhs_word;
+        }
+        self
     }
 }
 
-impl Display for ThreadSet {
+impl<const WORDS: usize> Display for ThreadBitSet<WORDS> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "ThreadSet({:#0width$b})", self.0, width = MAX_THREADS)
+        write!(f, "ThreadSet(")?;
+        for (index, word) in self.0.iter().rev().enumerate() {
+            if index > 0 {
+                write!(f, "_")?;
+            }
+            write!(f, "{:#0width$b}", word, width = THREADS_PER_WORD)?;
+        }
+        write!(f, ")")
     }
 }
 
-impl Debug for ThreadSet {
+impl<const WORDS: usize> Debug for ThreadBitSet<WORDS> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         Display::fmt(self, f)
     }
 }
 
-impl ThreadSet {
+impl<const WORDS: usize> ThreadBitSet<WORDS> {
+    /// Maximum number of threads that can be contained in the set.
+    pub(crate) const MAX_THREADS: usize = WORDS * THREADS_PER_WORD;
+
     #[inline(always)]
     pub(crate) const fn none() -> Self {
-        Self(0b0)
+        Self([0b0; WORDS])
     }
 
     #[inline(always)]
-    pub(crate) const fn any(num_threads: usize) -> Self {
-        if num_threads == MAX_THREADS {
-            Self(u64::MAX)
-        } else {
-            Self(Self::as_flag(num_threads) - 1)
+    pub const fn any(num_threads: usize) -> Self {
+        let mut words = [0b0; WORDS];
+        let mut index = 0;
+        while index < WORDS {
+            let word_start = index * THREADS_PER_WORD;
+            words[index] = if num_threads >= word_start + THREADS_PER_WORD {
+                u64::MAX
+            } else if num_threads > word_start {
+                Self::as_flag(num_threads - word_start) - 1
+            } else {
+                0b0
+            };
+            index += 1;
         }
+        Self(words)
     }
 
     #[inline(always)]
     pub(crate) const fn only(thread_id: ThreadId) -> Self {
-        Self(Self::as_flag(thread_id))
+        let mut words = [0b0; WORDS];
+        words[Self::word_index(thread_id)] = Self::as_flag(Self::bit_index(thread_id));
+        Self(words)
     }
 
     #[inline(always)]
     pub(crate) fn num_threads(&self) -> u32 {
-        self.0.count_ones()
+        self.0.iter().map(|word| word.count_ones()).sum()
     }
 
     #[inline(always)]
     pub(crate) fn only_one_contained(&self) -> Option<ThreadId> {
-        (self.num_threads() == 1).then_some(self.0.trailing_zeros() as ThreadId)
+        (self.num_threads() == 1).then(|| {
+            self.0
+                .iter()
+                .enumerate()
+                .find(|(_, word)| **word != 0)
+                .map(|(index, word)| index * THREADS_PER_WORD + word.trailing_zeros() as ThreadId)
+                .unwrap()
+        })
     }
 
     #[inline(always)]
@@ -374,27 +1017,37 @@ impl ThreadSet {
 
     #[inline(always)]
     pub(crate) fn contains(&self, thread_id: ThreadId) -> bool {
-        self.0 & Self::as_flag(thread_id) != 0
+        self.0[Self::word_index(thread_id)] & Self::as_flag(Self::bit_index(thread_id)) != 0
     }
 
     #[inline(always)]
     pub(crate) fn insert(&mut self, thread_id: ThreadId) {
-        self.0 |= Self::as_flag(thread_id);
+        self.0[Self::word_index(thread_id)] |= Self::as_flag(Self::bit_index(thread_id));
     }
 
     #[inline(always)]
     pub(crate) fn remove(&mut self, thread_id: ThreadId) {
-        self.0 &= !Self::as_flag(thread_id);
+        self.0[Self::word_index(thread_id)] &= !Self::as_flag(Self::bit_index(thread_id));
     }
 
     #[inline(always)]
-    pub(crate) fn contained_threads_iter(self) -> impl Iterator<Item = ThreadId> {
-        (0..MAX_THREADS).filter(move |thread_id| self.contains(*thread_id))
+    pub fn contained_threads_iter(self) -> impl Iterator<Item = ThreadId> {
+        (0..Self::MAX_THREADS).filter(move |thread_id| self.contains(*thread_id))
     }
 
     #[inline(always)]
-    const fn as_flag(thread_id: ThreadId) -> u64 {
-        0b1 << thread_id
+    const fn word_index(thread_id: ThreadId) -> usize {
+        thread_id / THREADS_PER_WORD
+    }
+
+    #[inline(always)]
+    const fn bit_index(thread_id: ThreadId) -> usize {
+        thread_id % THREADS_PER_WORD
+    }
+
+    #[inline(always)]
+    const fn as_flag(bit_index: usize) -> u64 {
+        0b1 << bit_index
     }
 }
 
@@ -493,6 +1146,121 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_try_lock_transaction_batch() {
+        let pk1 = Pubkey::new_unique();
+        let pk2 = Pubkey::new_unique();
+        let pk3 = Pubkey::new_unique();
+        let mut locks = ThreadAwareAccountLocks::new(TEST_NUM_THREADS);
+        locks.write_lock_account(&pk3, 2);
+
+        // Transactions within a batch may conflict with each other, since they
+        // are all locked on the same thread.
+        let batch = [
+            ([&pk1].into_iter(), [&pk2].into_iter()),
+            ([&pk2].into_iter(), [&pk3].into_iter()),
+        ];
+        assert_eq!(
+            locks.try_lock_transaction_batch(&batch, TEST_ANY_THREADS, test_thread_selector),
+            Ok(2)
+        );
+        assert_eq!(locks.write_locks.get(&pk1).unwrap().thread_id, 2);
+        assert_eq!(locks.write_locks.get(&pk2).unwrap().thread_id, 2);
+        assert_eq!(locks.read_locks.get(&pk3).unwrap().lock_count(2), 1);
+
+        locks.unlock_transaction_batch(&batch, 2);
+        assert!(!locks.write_locks.contains_key(&pk1));
+        assert!(!locks.write_locks.contains_key(&pk2));
+        assert!(locks.read_locks.is_empty());
+        assert_eq!(locks.write_locks.get(&pk3).unwrap().lock_count, 1);
+    }
+
+    #[test]
+    fn test_try_lock_transaction_batch_failure() {
+        let pk1 = Pubkey::new_unique();
+        let pk2 = Pubkey::new_unique();
+        let pk3 = Pubkey::new_unique();
+        let mut locks = ThreadAwareAccountLocks::new(TEST_NUM_THREADS);
+        locks.write_lock_account(&pk2, 0);
+        locks.write_lock_account(&pk3, 1);
+
+        // Second transaction can only be scheduled on 0, third only on 1.
+        let batch = [
+            ([&pk1].into_iter(), std::iter::empty()),
+            ([&pk2].into_iter(), std::iter::empty()),
+            ([&pk3].into_iter(), std::iter::empty()),
+        ];
+        assert_eq!(
+            locks.try_lock_transaction_batch(&batch, TEST_ANY_THREADS, test_thread_selector),
+            Err(BatchLockError::Unschedulable(2))
+        );
+        // No partial locks are left behind.
+        assert!(!locks.write_locks.contains_key(&pk1));
+        assert_eq!(locks.write_locks.get(&pk2).unwrap().lock_count, 1);
+        assert_eq!(locks.write_locks.get(&pk3).unwrap().lock_count, 1);
+
+        // Restricting allowed threads makes the second transaction fail.
+        assert_eq!(
+            locks.try_lock_transaction_batch(&batch[..2], ThreadSet::only(1), test_thread_selector),
+            Err(BatchLockError::Unschedulable(1))
+        );
+        assert_eq!(
+            locks.try_lock_transaction_batch(&batch, ThreadSet::none(), test_thread_selector),
+            Err(BatchLockError::NoAllowedThreads)
+        );
+        assert_eq!(
+            locks.try_lock_transaction_batch(&batch[..0], TEST_ANY_THREADS, test_thread_selector),
+            Err(BatchLockError::EmptyBatch)
+        );
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let pk1 = Pubkey::new_unique();
+        let pk2 = Pubkey::new_unique();
+        let pk3 = Pubkey::new_unique();
+        let mut locks = ThreadAwareAccountLocks::new(TEST_NUM_THREADS);
+        assert_eq!(
+            locks.snapshot(2),
+            LockTableSnapshot {
+                hottest_write_locked_accounts: vec![],
+                write_lock_counts: vec![0; TEST_NUM_THREADS],
+                read_lock_counts: vec![0; TEST_NUM_THREADS],
+                num_write_locked_accounts: 0,
+                num_read_locked_accounts: 0,
+            }
+        );
+
+        locks.write_lock_account(&pk1, 0);
+        locks.write_lock_account(&pk2, 1);
+        locks.write_lock_account(&pk2, 1);
+        locks.write_lock_account(&pk3, 1);
+        locks.read_lock_account(&pk3, 1);
+        locks.read_lock_account(&pk1, 0);
+        locks.read_lock_account(&pk1, 0);
+
+        let snapshot = locks.snapshot(2);
+        assert_eq!(
+            snapshot.hottest_write_locked_accounts[0],
+            WriteLockedAccount {
+                account: pk2,
+                thread_id: 1,
+                lock_count: 2,
+            }
+        );
+        assert_eq!(snapshot.hottest_write_locked_accounts.len(), 2);
+        assert_eq!(snapshot.hottest_write_locked_accounts[1].lock_count, 1);
+        assert_eq!(snapshot.write_lock_counts, vec![1, 3, 0, 0]);
+        assert_eq!(snapshot.read_lock_counts, vec![2, 1, 0, 0]);
+        assert_eq!(snapshot.num_write_locked_accounts, 3);
+        assert_eq!(snapshot.num_read_locked_accounts, 2);
+
+        assert!(locks.snapshot(0).hottest_write_locked_accounts.is_empty());
+        let hottest_write_locked_accounts = locks.snapshot(4).hottest_write_locked_accounts;
+        assert_eq!(hottest_write_locked_accounts.len(), 3);
+        assert_eq!(hottest_write_locked_accounts[0].account, pk2);
+    }
+
     #[test]
     fn test_accounts_schedulable_threads_no_outstanding_locks() {
         let pk1 = Pubkey::new_unique();
@@ -631,6 +1399,274 @@ mod tests {
         locks.read_unlock_account(&pk1, 1);
     }
 
+    #[test]
+    fn test_try_write_lock_account() {
+        let pk1 = Pubkey::new_unique();
+        let mut locks = ThreadAwareAccountLocks::new(TEST_NUM_THREADS);
+        assert_eq!(locks.try_write_lock_account(&pk1, 0), Ok(()));
+        assert_eq!(
+            locks.try_write_lock_account(&pk1, 1),
+            Err(LockError::WriteLockedOnOtherThread {
+                account: pk1,
+                thread_id: 0
+            })
+        );
+        assert_eq!(
+            locks.try_write_lock_account(&pk1, TEST_NUM_THREADS),
+            Err(LockError::InvalidThread(TEST_NUM_THREADS))
+        );
+        assert_eq!(locks.write_locks.get(&pk1).unwrap().lock_count, 1);
+    }
+
+    #[test]
+    fn test_try_write_lock_account_read_conflict() {
+        let pk1 = Pubkey::new_unique();
+        let mut locks = ThreadAwareAccountLocks::new(TEST_NUM_THREADS);
+        locks.read_lock_account(&pk1, 0);
+        assert_eq!(
+            locks.try_write_lock_account(&pk1, 1),
+            Err(LockError::ReadLockedOnOtherThread {
+                account: pk1,
+                thread_set: ThreadSet::only(0)
+            })
+        );
+        // failed lock must not leave a write lock behind
+        assert!(locks.write_locks.is_empty());
+    }
+
+    #[test]
+    fn test_try_read_lock_account() {
+        let pk1 = Pubkey::new_unique();
+        let mut locks = ThreadAwareAccountLocks::new(TEST_NUM_THREADS);
+        locks.write_lock_account(&pk1, 2);
+        assert_eq!(locks.try_read_lock_account(&pk1, 2), Ok(()));
+        assert_eq!(
+            locks.try_read_lock_account(&pk1, 1),
+            Err(LockError::WriteLockedOnOtherThread {
+                account: pk1,
+                thread_id: 2
+            })
+        );
+        assert_eq!(
+            locks.read_locks.get(&pk1).unwrap().thread_set,
+            ThreadSet::only(2)
+        );
+    }
+
+    #[test]
+    fn test_try_unlock_account() {
+        let pk1 = Pubkey::new_unique();
+        let pk2 = Pubkey::new_unique();
+        let mut locks = ThreadAwareAccountLocks::new(TEST_NUM_THREADS);
+        assert_eq!(
+            locks.try_write_unlock_account(&pk1, 0),
+            Err(LockError::WriteLockNotFound(pk1))
+        );
+        assert_eq!(
+            locks.try_read_unlock_account(&pk2, 0),
+            Err(LockError::ReadLockNotFound(pk2))
+        );
+
+        locks.write_lock_account(&pk1, 0);
+        locks.read_lock_account(&pk2, 0);
+        assert_eq!(
+            locks.try_write_unlock_account(&pk1, 1),
+            Err(LockError::WriteLockedOnOtherThread {
+                account: pk1,
+                thread_id: 0
+            })
+        );
+        assert_eq!(
+            locks.try_read_unlock_account(&pk2, 1),
+            Err(LockError::ReadLockedOnOtherThread {
+                account: pk2,
+                thread_set: ThreadSet::only(0)
+            })
+        );
+
+        assert_eq!(locks.try_write_unlock_account(&pk1, 0), Ok(()));
+        assert_eq!(locks.try_read_unlock_account(&pk2, 0), Ok(()));
+        assert!(locks.write_locks.is_empty());
+        assert!(locks.read_locks.is_empty());
+    }
+
+    #[test]
+    fn test_upgrade_schedulable_threads() {
+        let pk1 = Pubkey::new_unique();
+        let mut locks = ThreadAwareAccountLocks::new(TEST_NUM_THREADS);
+
+        // No read locks - nothing to upgrade.
+        assert_eq!(locks.upgrade_schedulable_threads(&pk1), ThreadSet::none());
+
+        // Single read-lock holder can upgrade.
+        locks.read_lock_account(&pk1, 2);
+        assert_eq!(locks.upgrade_schedulable_threads(&pk1), ThreadSet::only(2));
+
+        // Multiple read-lock holders cannot upgrade.
+        locks.read_lock_account(&pk1, 3);
+        assert_eq!(locks.upgrade_schedulable_threads(&pk1), ThreadSet::none());
+
+        // Write-locked only - nothing to upgrade.
+        let pk2 = Pubkey::new_unique();
+        locks.write_lock_account(&pk2, 2);
+        assert_eq!(locks.upgrade_schedulable_threads(&pk2), ThreadSet::none());
+    }
+
+    #[test]
+    fn test_try_upgrade_read_lock() {
+        let pk1 = Pubkey::new_unique();
+        let mut locks = ThreadAwareAccountLocks::new(TEST_NUM_THREADS);
+
+        assert_eq!(
+            locks.try_upgrade_read_lock(&pk1, 2),
+            Err(LockError::ReadLockNotFound(pk1))
+        );
+
+        locks.read_lock_account(&pk1, 2);
+        locks.read_lock_account(&pk1, 2);
+        assert_eq!(
+            locks.try_upgrade_read_lock(&pk1, 3),
+            Err(LockError::ReadLockedOnOtherThread {
+                account: pk1,
+                thread_set: ThreadSet::only(2),
+            })
+        );
+        assert_eq!(
+            locks.try_upgrade_read_lock(&pk1, TEST_NUM_THREADS),
+            Err(LockError::InvalidThread(TEST_NUM_THREADS))
+        );
+
+        // Upgrade one of the two read locks.
+        assert_eq!(locks.try_upgrade_read_lock(&pk1, 2), Ok(()));
+        assert_eq!(locks.read_locks.get(&pk1).unwrap().lock_count(2), 1);
+        assert_eq!(locks.write_locks.get(&pk1).unwrap().lock_count, 1);
+        assert_eq!(locks.write_schedulable_threads(&pk1), ThreadSet::only(2));
+        assert_eq!(locks.read_schedulable_threads(&pk1), ThreadSet::only(2));
+
+        // Upgrade the remaining read lock.
+        assert_eq!(locks.try_upgrade_read_lock(&pk1, 2), Ok(()));
+        assert!(!locks.read_locks.contains_key(&pk1));
+        assert_eq!(locks.write_locks.get(&pk1).unwrap().lock_count, 2);
+
+        // Upgrade fails if another thread also holds a read lock.
+        let pk2 = Pubkey::new_unique();
+        locks.read_lock_account(&pk2, 2);
+        locks.read_lock_account(&pk2, 3);
+        let mut thread_set = ThreadSet::only(2);
+        thread_set.insert(3);
+        assert_eq!(
+            locks.try_upgrade_read_lock(&pk2, 2),
+            Err(LockError::ReadLockedOnOtherThread {
+                account: pk2,
+                thread_set,
+            })
+        );
+        assert!(!locks.write_locks.contains_key(&pk2));
+    }
+
+    #[test]
+    fn test_try_lock_accounts_with_upgrades() {
+        let pk1 = Pubkey::new_unique();
+        let pk2 = Pubkey::new_unique();
+        let mut locks = ThreadAwareAccountLocks::new(TEST_NUM_THREADS);
+        locks.read_lock_account(&pk1, 2);
+        locks.read_lock_account(&pk1, 3);
+
+        // Upgrade is not schedulable while multiple threads hold read locks.
+        assert_eq!(
+            locks.try_lock_accounts_with_upgrades(
+                [&pk2].into_iter(),
+                std::iter::empty(),
+                [&pk1].into_iter(),
+                ThreadSet::any(TEST_NUM_THREADS),
+                test_thread_selector
+            ),
+            None
+        );
+        assert!(!locks.write_locks.contains_key(&pk2));
+
+        // Upgrade is only schedulable on the sole read-lock holder.
+        locks.read_unlock_account(&pk1, 3);
+        let mut allowed_threads = ThreadSet::any(TEST_NUM_THREADS);
+        allowed_threads.remove(2);
+        assert_eq!(
+            locks.try_lock_accounts_with_upgrades(
+                [&pk2].into_iter(),
+                std::iter::empty(),
+                [&pk1].into_iter(),
+                allowed_threads,
+                test_thread_selector
+            ),
+            None
+        );
+        assert_eq!(
+            locks.try_lock_accounts_with_upgrades(
+                [&pk2].into_iter(),
+                std::iter::empty(),
+                [&pk1].into_iter(),
+                ThreadSet::any(TEST_NUM_THREADS),
+                test_thread_selector
+            ),
+            Some(2)
+        );
+        assert!(!locks.read_locks.contains_key(&pk1));
+        assert_eq!(locks.write_locks.get(&pk1).unwrap().thread_id, 2);
+        assert_eq!(locks.write_locks.get(&pk2).unwrap().thread_id, 2);
+    }
+
+    #[test]
+    fn test_try_lock_accounts_with_upgrades_validation() {
+        let pk1 = Pubkey::new_unique();
+        let pk2 = Pubkey::new_unique();
+        let mut locks = ThreadAwareAccountLocks::new(TEST_NUM_THREADS);
+        locks.read_lock_account(&pk1, 2);
+
+        // Upgrading an account twice requires two read locks.
+        assert_eq!(
+            locks.try_lock_accounts_with_upgrades(
+                [&pk2].into_iter(),
+                std::iter::empty(),
+                [&pk1, &pk1].into_iter(),
+                ThreadSet::any(TEST_NUM_THREADS),
+                test_thread_selector
+            ),
+            None
+        );
+        assert!(!locks.write_locks.contains_key(&pk2));
+        assert_eq!(locks.read_locks.get(&pk1).unwrap().lock_count(2), 1);
+
+        // Upgraded accounts may not also be in the write or read locks.
+        for (write_account_locks, read_account_locks) in [([&pk1], [&pk2]), ([&pk2], [&pk1])] {
+            assert_eq!(
+                locks.try_lock_accounts_with_upgrades(
+                    write_account_locks.into_iter(),
+                    read_account_locks.into_iter(),
+                    [&pk1].into_iter(),
+                    ThreadSet::any(TEST_NUM_THREADS),
+                    test_thread_selector
+                ),
+                None
+            );
+        }
+        assert!(!locks.write_locks.contains_key(&pk1));
+        assert!(!locks.write_locks.contains_key(&pk2));
+        assert!(!locks.read_locks.contains_key(&pk2));
+
+        locks.read_lock_account(&pk1, 2);
+        assert_eq!(
+            locks.try_lock_accounts_with_upgrades(
+                [&pk2].into_iter(),
+                std::iter::empty(),
+                [&pk1, &pk1].into_iter(),
+                ThreadSet::any(TEST_NUM_THREADS),
+                test_thread_selector
+            ),
+            Some(2)
+        );
+        assert!(!locks.read_locks.contains_key(&pk1));
+        assert_eq!(locks.write_locks.get(&pk1).unwrap().lock_count, 2);
+    }
+
     #[test]
     fn test_write_locking() {
         let pk1 = Pubkey::new_unique();
@@ -653,6 +1689,54 @@ mod tests {
         assert!(locks.read_locks.is_empty());
     }
 
+    #[test]
+    fn test_unlock_batch() {
+        let pk1 = Pubkey::new_unique();
+        let pk2 = Pubkey::new_unique();
+        let pk3 = Pubkey::new_unique();
+        let mut locks = ThreadAwareAccountLocks::new(TEST_NUM_THREADS);
+
+        // Batch of three transactions on thread 1:
+        //   1. write pk1, read pk3
+        //   2. write pk1, read pk2
+        //   3. write pk2
+        let write_locks = [&pk1, &pk1, &pk2];
+        let read_locks = [&pk3, &pk2];
+        locks.lock_accounts(write_locks.into_iter(), read_locks.into_iter(), 1);
+        assert_eq!(locks.write_locks.get(&pk1).unwrap().lock_count, 2);
+
+        locks.unlock_batch(
+            [(&pk1, 2), (&pk2, 1)].into_iter(),
+            [(&pk3, 1), (&pk2, 1)].into_iter(),
+            1,
+        );
+        assert!(locks.write_locks.is_empty());
+        assert!(locks.read_locks.is_empty());
+    }
+
+    #[test]
+    fn test_unlock_batch_partial() {
+        let pk1 = Pubkey::new_unique();
+        let mut locks = ThreadAwareAccountLocks::new(TEST_NUM_THREADS);
+        locks.read_lock_account(&pk1, 2);
+        locks.read_lock_account(&pk1, 2);
+        locks.read_lock_account(&pk1, 2);
+
+        locks.unlock_batch(std::iter::empty(), [(&pk1, 2)].into_iter(), 2);
+        let read_locks = locks.read_locks.get(&pk1).unwrap();
+        assert_eq!(read_locks.thread_set, ThreadSet::only(2));
+        assert_eq!(read_locks.lock_count(2), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot unlock more write locks than are held")]
+    fn test_unlock_batch_too_many_write_unlocks() {
+        let pk1 = Pubkey::new_unique();
+        let mut locks = ThreadAwareAccountLocks::new(TEST_NUM_THREADS);
+        locks.write_lock_account(&pk1, 0);
+        locks.unlock_batch([(&pk1, 2)].into_iter(), std::iter::empty(), 0);
+    }
+
     #[test]
     #[should_panic(expected = "thread_id must be < num_threads")]
     fn test_lock_accounts_invalid_thread() {
@@ -661,6 +1745,90 @@ mod tests {
         locks.lock_accounts([&pk1].into_iter(), std::iter::empty(), TEST_NUM_THREADS);
     }
 
+    #[test]
+    fn test_shrink_to_fit() {
+        const NUM_ACCOUNTS: usize = 1_000;
+        let accounts: Vec<_> = (0..NUM_ACCOUNTS).map(|_| Pubkey::new_unique()).collect();
+        let mut locks = ThreadAwareAccountLocks::with_capacity(TEST_NUM_THREADS, 16, 16);
+        assert!(locks.write_locks.capacity() >= 16);
+        assert!(locks.read_locks.capacity() >= 16);
+
+        locks.lock_accounts(accounts[..10].iter(), accounts[10..].iter(), 0);
+        locks.shrink_to_fit();
+        assert!(locks.write_locks.capacity() >= 10);
+        assert!(locks.read_locks.capacity() >= NUM_ACCOUNTS - 10);
+
+        locks.unlock_accounts(accounts[..10].iter(), accounts[10..].iter(), 0);
+        assert!(locks.read_locks.capacity() >= NUM_ACCOUNTS - 10);
+        locks.shrink_to_fit();
+        assert!(locks.read_locks.capacity() >= 16);
+        assert!(locks.read_locks.capacity() < NUM_ACCOUNTS - 10);
+    }
+
+    #[test]
+    fn test_read_lock_counts() {
+        let mut lock_counts = ReadLockCounts::new();
+        let num_threads = INLINE_READ_LOCK_THREADS + 2;
+        for thread_id in 0..num_threads {
+            for _ in 0..=thread_id {
+                lock_counts.increment(thread_id);
+            }
+        }
+        assert_eq!(lock_counts.num_inline, INLINE_READ_LOCK_THREADS);
+        assert_eq!(lock_counts.spilled.len(), 2);
+        for thread_id in 0..num_threads {
+            assert_eq!(lock_counts.get(thread_id), thread_id as LockCount + 1);
+        }
+        assert_eq!(lock_counts.get(num_threads), 0);
+
+        // Removing an inline entry refills from the spilled entries.
+        assert_eq!(lock_counts.decrement(1, 1), 1);
+        assert_eq!(lock_counts.decrement(1, 1), 0);
+        assert_eq!(lock_counts.num_inline, INLINE_READ_LOCK_THREADS);
+        assert_eq!(lock_counts.spilled.len(), 1);
+
+        // Removing a spilled entry.
+        let spilled_thread_id = lock_counts.spilled[0].0;
+        let spilled_count = lock_counts.get(spilled_thread_id);
+        assert_eq!(lock_counts.decrement(spilled_thread_id, spilled_count), 0);
+        assert!(lock_counts.spilled.is_empty());
+
+        for thread_id in (0..num_threads).filter(|id| *id != 1 && *id != spilled_thread_id) {
+            assert_eq!(lock_counts.get(thread_id), thread_id as LockCount + 1);
+            assert_eq!(
+                lock_counts.decrement(thread_id, thread_id as LockCount + 1),
+                0
+            );
+        }
+        assert_eq!(lock_counts.num_inline, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "read lock count must be at least count")]
+    fn test_read_lock_counts_decrement_too_many() {
+        let mut lock_counts = ReadLockCounts::new();
+        lock_counts.increment(0);
+        lock_counts.decrement(0, 2);
+    }
+
+    #[test]
+    fn test_read_locking_many_threads() {
+        let pk1 = Pubkey::new_unique();
+        let mut locks = ThreadAwareAccountLocks::new(MAX_THREADS);
+        for thread_id in 0..MAX_THREADS {
+            locks.read_lock_account(&pk1, thread_id);
+        }
+        assert_eq!(
+            locks.read_locks.get(&pk1).unwrap().thread_set,
+            ThreadSet::any(MAX_THREADS)
+        );
+        for thread_id in (0..MAX_THREADS).rev() {
+            assert_eq!(locks.read_locks.get(&pk1).unwrap().lock_count(thread_id), 1);
+            locks.read_unlock_account(&pk1, thread_id);
+        }
+        assert!(locks.read_locks.is_empty());
+    }
+
     #[test]
     fn test_thread_set() {
         let mut thread_set = ThreadSet::none();
@@ -696,6 +1864,97 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_thread_bit_set_multiple_words() {
+        let mut thread_set = ThreadBitSet::<2>::none();
+        assert!(thread_set.is_empty());
+        assert_eq!(ThreadBitSet::<2>::MAX_THREADS, 2 * MAX_THREADS);
+
+        thread_set.insert(70);
+        assert_eq!(thread_set.num_threads(), 1);
+        assert_eq!(thread_set.only_one_contained(), Some(70));
+        assert!(thread_set.contains(70));
+        assert!(!thread_set.contains(6));
+
+        thread_set.insert(6);
+        assert_eq!(thread_set.num_threads(), 2);
+        assert_eq!(thread_set.only_one_contained(), None);
+        assert_eq!(
+            thread_set.contained_threads_iter().collect::<Vec<_>>(),
+            vec![6, 70]
+        );
+
+        let any_threads = ThreadBitSet::<2>::any(80);
+        assert_eq!(any_threads.num_threads(), 80);
+        assert!(any_threads.contains(79));
+        assert!(!any_threads.contains(80));
+        assert_eq!(
+            (any_threads - ThreadBitSet::only(6)) & thread_set,
+            ThreadBitSet::only(70)
+        );
+
+        thread_set.remove(6);
+        assert_eq!(thread_set, ThreadBitSet::only(70));
+    }
+
+    #[test]
+    fn test_thread_set_display() {
+        assert_eq!(
+            ThreadSet::only(1).to_string(),
+            format!("ThreadSet({:#0width$b})", 0b10, width = MAX_THREADS)
+        );
+        assert_eq!(
+            ThreadBitSet::<2>::only(64).to_string(),
+            format!(
+                "ThreadSet({:#0width$b}_{:#0width$b})",
+                0b1,
+                0b0,
+                width = MAX_THREADS
+            )
+        );
+    }
+
+    #[test]
+    fn test_locks_multiple_words() {
+        let num_threads = MAX_THREADS + 4;
+        let pk1 = Pubkey::new_unique();
+        let pk2 = Pubkey::new_unique();
+        let mut locks = ThreadAwareAccountLocks::<2>::new_with_words(num_threads);
+
+        locks.write_lock_account(&pk1, 66);
+        locks.read_lock_account(&pk2, 66);
+        locks.read_lock_account(&pk2, 1);
+        assert_eq!(
+            locks.try_lock_accounts(
+                [&pk1].into_iter(),
+                std::iter::empty(),
+                ThreadBitSet::any(num_threads),
+                |thread_set| thread_set.contained_threads_iter().next().unwrap(),
+            ),
+            Some(66)
+        );
+        assert_eq!(
+            locks.try_lock_accounts(
+                [&pk2].into_iter(),
+                std::iter::empty(),
+                ThreadBitSet::any(num_threads),
+                |thread_set| thread_set.contained_threads_iter().next().unwrap(),
+            ),
+            None
+        );
+
+        locks.unlock_accounts([&pk1, &pk1].into_iter(), [&pk2].into_iter(), 66);
+        locks.read_unlock_account(&pk2, 1);
+        assert!(locks.write_locks.is_empty());
+        assert!(locks.read_locks.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "num threads must be <= 128")]
+    fn test_too_many_num_threads_multiple_words() {
+        ThreadAwareAccountLocks::<2>::new_with_words(2 * MAX_THREADS + 1);
+    }
+
     #[test]
     fn test_thread_set_any_zero() {
         let any_threads = ThreadSet::any(0);
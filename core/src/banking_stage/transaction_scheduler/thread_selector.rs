@@ -0,0 +1,402 @@
+use {
+    super::thread_aware_account_locks::{ThreadBitSet, ThreadId},
+    solana_sdk::pubkey::Pubkey,
+};
+#[cfg(test)]
+use {
+    rand::{Rng, SeedableRng},
+    rand_chacha::ChaChaRng,
+};
+
+/// Strategy used to select a thread from the set of threads a transaction
+/// can be scheduled on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ThreadSelectionStrategy {
+    /// Select the thread with the fewest outstanding compute units.
+    LeastLoadedByCus,
+    /// Select the thread with the fewest outstanding transactions.
+    LeastLoadedByCount,
+    /// Cycle through threads in order.
+    RoundRobin,
+    /// Prefer a thread derived from the fee-payer, so that transactions from
+    /// the same payer tend to land on the same thread. Falls back to the least
+    /// loaded thread (by count) if the preferred thread is not schedulable.
+    StickyByFeePayer,
+    /// Select uniformly at random from a generator seeded with the given
+    /// seed, so that selections are reproducible across runs. Only available
+    /// in tests.
+    #[cfg(test)]
+    SeededRandom(u64),
+}
+
+impl ThreadSelectionStrategy {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::LeastLoadedByCus => "least_loaded_by_cus",
+            Self::LeastLoadedByCount => "least_loaded_by_count",
+            Self::RoundRobin => "round_robin",
+            Self::StickyByFeePayer => "sticky_by_fee_payer",
+            #[cfg(test)]
+            Self::SeededRandom(_) => "seeded_random",
+        }
+    }
+}
+
+/// Outstanding work on each thread, used by load-based strategies.
+pub(crate) struct ThreadLoads {
+    transaction_counts: Vec<usize>,
+    cus: Vec<u64>,
+}
+
+impl ThreadLoads {
+    pub(crate) fn new(num_threads: usize) -> Self {
+        Self {
+            transaction_counts: vec![0; num_threads],
+            cus: vec![0; num_threads],
+        }
+    }
+
+    /// Add `num_transactions` using a total of `cus` to `thread_id`.
+    pub(crate) fn add(&mut self, thread_id: ThreadId, num_transactions: usize, cus: u64) {
+        self.transaction_counts[thread_id] += num_transactions;
+        self.cus[thread_id] += cus;
+    }
+
+    /// Remove `num_transactions` using a total of `cus` from `thread_id`.
+    /// Panics if more work is removed than is outstanding.
+    pub(crate) fn remove(&mut self, thread_id: ThreadId, num_transactions: usize, cus: u64) {
+        self.transaction_counts[thread_id] = self.transaction_counts[thread_id]
+            .checked_sub(num_transactions)
+            .expect("removed transactions must be outstanding");
+        self.cus[thread_id] = self.cus[thread_id]
+            .checked_sub(cus)
+            .expect("removed cus must be outstanding");
+    }
+
+    pub(crate) fn transaction_count(&self, thread_id: ThreadId) -> usize {
+        self.transaction_counts[thread_id]
+    }
+
+    pub(crate) fn cus(&self, thread_id: ThreadId) -> u64 {
+        self.cus[thread_id]
+    }
+}
+
+/// Selects threads for `ThreadAwareAccountLocks::try_lock_accounts` according
+/// to a configured strategy, and tracks how selections are distributed.
+pub(crate) struct ThreadSelector {
+    strategy: ThreadSelectionStrategy,
+    num_threads: usize,
+    next_round_robin_thread: ThreadId,
+    /// Only used by `ThreadSelectionStrategy::SeededRandom`.
+    #[cfg(test)]
+    rng: Option<ChaChaRng>,
+    metrics: ThreadSelectorMetrics,
+}
+
+impl ThreadSelector {
+    pub(crate) fn new(strategy: ThreadSelectionStrategy, num_threads: usize) -> Self {
+        assert!(num_threads > 0, "num threads must be > 0");
+        #[cfg(test)]
+        let rng = match strategy {
+            ThreadSelectionStrategy::SeededRandom(seed) => Some(ChaChaRng::seed_from_u64(seed)),
+            _ => None,
+        };
+        Self {
+            strategy,
+            num_threads,
+            next_round_robin_thread: 0,
+            #[cfg(test)]
+            rng,
+            metrics: ThreadSelectorMetrics::new(num_threads),
+        }
+    }
+
+    pub(crate) fn strategy(&self) -> ThreadSelectionStrategy {
+        self.strategy
+    }
+
+    /// Select a thread from the non-empty `thread_set`.
+    /// `fee_payer` is only used by `ThreadSelectionStrategy::StickyByFeePayer`.
+    pub(crate) fn select<const WORDS: usize>(
+        &mut self,
+        thread_set: ThreadBitSet<WORDS>,
+        loads: &ThreadLoads,
+        fee_payer: &Pubkey,
+    ) -> ThreadId {
+        assert!(!thread_set.is_empty(), "thread_set must not be empty");
+
+        let thread_id = if let Some(thread_id) = thread_set.only_one_contained() {
+            self.metrics.num_single_candidate += 1;
+            thread_id
+        } else {
+            match self.strategy {
+                ThreadSelectionStrategy::LeastLoadedByCus => {
+                    Self::least_loaded(thread_set, |thread_id| loads.cus(thread_id))
+                }
+                ThreadSelectionStrategy::LeastLoadedByCount => {
+                    Self::least_loaded(thread_set, |thread_id| loads.transaction_count(thread_id))
+                }
+                ThreadSelectionStrategy::RoundRobin => self.round_robin(thread_set),
+                ThreadSelectionStrategy::StickyByFeePayer => {
+                    let preferred_thread = self.preferred_thread(fee_payer);
+                    if thread_set.contains(preferred_thread) {
+                        self.metrics.num_sticky_hits += 1;
+                        preferred_thread
+                    } else {
+                        Self::least_loaded(thread_set, |thread_id| {
+                            loads.transaction_count(thread_id)
+                        })
+                    }
+                }
+                #[cfg(test)]
+                ThreadSelectionStrategy::SeededRandom(_) => self.random(thread_set),
+            }
+        };
+
+        self.metrics.num_selections[thread_id] += 1;
+        thread_id
+    }
+
+    /// Report and reset the selection metrics.
+    pub(crate) fn report_metrics(&mut self) {
+        let metrics = std::mem::replace(
+            &mut self.metrics,
+            ThreadSelectorMetrics::new(self.num_threads),
+        );
+        let selections = &metrics.num_selections;
+        let total_selections: u64 = selections.iter().sum();
+        if total_selections == 0 {
+            return;
+        }
+        let max_thread_selections = selections.iter().copied().max().unwrap_or_default();
+        let min_thread_selections = selections.iter().copied().min().unwrap_or_default();
+
+        datapoint_info!(
+            "banking_stage_scheduler_thread_selector",
+            ("strategy", self.strategy.as_str(), String),
+            ("num_selections", total_selections, i64),
+            ("num_single_candidate", metrics.num_single_candidate, i64),
+            ("num_sticky_hits", metrics.num_sticky_hits, i64),
+            ("max_thread_selections", max_thread_selections, i64),
+            ("min_thread_selections", min_thread_selections, i64),
+        );
+    }
+
+    /// Select the thread in `thread_set` with the lowest load.
+    /// Ties are broken by the lowest thread id.
+    fn least_loaded<L: Ord, const WORDS: usize>(
+        thread_set: ThreadBitSet<WORDS>,
+        load: impl Fn(ThreadId) -> L,
+    ) -> ThreadId {
+        thread_set
+            .contained_threads_iter()
+            .min_by_key(|thread_id| load(*thread_id))
+            .unwrap()
+    }
+
+    /// Select the first thread in `thread_set` at or after the next
+    /// round-robin thread, wrapping around.
+    fn round_robin<const WORDS: usize>(&mut self, thread_set: ThreadBitSet<WORDS>) -> ThreadId {
+        let thread_id = (self.next_round_robin_thread..self.num_threads)
+            .chain(0..self.next_round_robin_thread)
+            .find(|thread_id| thread_set.contains(*thread_id))
+            .unwrap();
+        self.next_round_robin_thread = (thread_id + 1) % self.num_threads;
+        thread_id
+    }
+
+    /// Select a thread from `thread_set` uniformly at random.
+    #[cfg(test)]
+    fn random<const WORDS: usize>(&mut self, thread_set: ThreadBitSet<WORDS>) -> ThreadId {
+        let rng = self
+            .rng
+            .as_mut()
+            .expect("rng must exist for seeded random selection");
+        let index = rng.gen_range(0, thread_set.num_threads() as usize);
+        thread_set.contained_threads_iter().nth(index).unwrap()
+    }
+
+    /// The thread transactions paid for by `fee_payer` prefer to run on.
+    fn preferred_thread(&self, fee_payer: &Pubkey) -> ThreadId {
+        let bytes = fee_payer.to_bytes();
+        let hash = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+        (hash % self.num_threads as u64) as ThreadId
+    }
+}
+
+struct ThreadSelectorMetrics {
+    /// Number of selections made for each thread.
+    num_selections: Vec<u64>,
+    /// Number of selections where only a single thread was schedulable.
+    num_single_candidate: u64,
+    /// Number of selections where the fee-payer's preferred thread was used.
+    num_sticky_hits: u64,
+}
+
+impl ThreadSelectorMetrics {
+    fn new(num_threads: usize) -> Self {
+        Self {
+            num_selections: vec![0; num_threads],
+            num_single_candidate: 0,
+            num_sticky_hits: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::banking_stage::transaction_scheduler::thread_aware_account_locks::ThreadSet,
+    };
+
+    const TEST_NUM_THREADS: usize = 4;
+    const TEST_ANY_THREADS: ThreadSet = ThreadSet::any(TEST_NUM_THREADS);
+
+    #[test]
+    fn test_single_candidate() {
+        let loads = ThreadLoads::new(TEST_NUM_THREADS);
+        for strategy in [
+            ThreadSelectionStrategy::LeastLoadedByCus,
+            ThreadSelectionStrategy::LeastLoadedByCount,
+            ThreadSelectionStrategy::RoundRobin,
+            ThreadSelectionStrategy::StickyByFeePayer,
+            ThreadSelectionStrategy::SeededRandom(0),
+        ] {
+            let mut selector = ThreadSelector::new(strategy, TEST_NUM_THREADS);
+            assert_eq!(
+                selector.select(ThreadSet::only(2), &loads, &Pubkey::new_unique()),
+                2
+            );
+            assert_eq!(selector.metrics.num_single_candidate, 1);
+        }
+    }
+
+    #[test]
+    fn test_least_loaded_by_cus() {
+        let mut loads = ThreadLoads::new(TEST_NUM_THREADS);
+        loads.add(0, 1, 1_000);
+        loads.add(1, 10, 100);
+        loads.add(2, 1, 500);
+        let mut selector =
+            ThreadSelector::new(ThreadSelectionStrategy::LeastLoadedByCus, TEST_NUM_THREADS);
+        let pk = Pubkey::new_unique();
+
+        assert_eq!(selector.select(TEST_ANY_THREADS, &loads, &pk), 3);
+        let thread_set = TEST_ANY_THREADS - ThreadSet::only(3);
+        assert_eq!(selector.select(thread_set, &loads, &pk), 1);
+
+        loads.remove(0, 1, 1_000);
+        assert_eq!(selector.select(thread_set, &loads, &pk), 0);
+    }
+
+    #[test]
+    fn test_least_loaded_by_count() {
+        let mut loads = ThreadLoads::new(TEST_NUM_THREADS);
+        loads.add(0, 1, 1_000);
+        loads.add(1, 10, 100);
+        loads.add(2, 2, 500);
+        loads.add(3, 3, 0);
+        let mut selector = ThreadSelector::new(
+            ThreadSelectionStrategy::LeastLoadedByCount,
+            TEST_NUM_THREADS,
+        );
+        let pk = Pubkey::new_unique();
+
+        assert_eq!(selector.select(TEST_ANY_THREADS, &loads, &pk), 0);
+        let thread_set = TEST_ANY_THREADS - ThreadSet::only(0);
+        assert_eq!(selector.select(thread_set, &loads, &pk), 2);
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let loads = ThreadLoads::new(TEST_NUM_THREADS);
+        let mut selector =
+            ThreadSelector::new(ThreadSelectionStrategy::RoundRobin, TEST_NUM_THREADS);
+        let pk = Pubkey::new_unique();
+
+        assert_eq!(selector.select(TEST_ANY_THREADS, &loads, &pk), 0);
+        assert_eq!(selector.select(TEST_ANY_THREADS, &loads, &pk), 1);
+        // thread 2 is not schedulable, so skip to 3
+        let thread_set = TEST_ANY_THREADS - ThreadSet::only(2);
+        assert_eq!(selector.select(thread_set, &loads, &pk), 3);
+        // wrap around
+        assert_eq!(selector.select(TEST_ANY_THREADS, &loads, &pk), 0);
+    }
+
+    #[test]
+    fn test_sticky_by_fee_payer() {
+        let loads = ThreadLoads::new(TEST_NUM_THREADS);
+        let mut selector =
+            ThreadSelector::new(ThreadSelectionStrategy::StickyByFeePayer, TEST_NUM_THREADS);
+        let pk = Pubkey::new_unique();
+        let preferred_thread = selector.preferred_thread(&pk);
+
+        assert_eq!(
+            selector.select(TEST_ANY_THREADS, &loads, &pk),
+            preferred_thread
+        );
+        assert_eq!(
+            selector.select(TEST_ANY_THREADS, &loads, &pk),
+            preferred_thread
+        );
+        assert_eq!(selector.metrics.num_sticky_hits, 2);
+
+        // preferred thread is not schedulable - fall back to least loaded
+        let thread_set = TEST_ANY_THREADS - ThreadSet::only(preferred_thread);
+        let selected_thread = selector.select(thread_set, &loads, &pk);
+        assert_ne!(selected_thread, preferred_thread);
+        assert_eq!(selector.metrics.num_sticky_hits, 2);
+    }
+
+    #[test]
+    fn test_seeded_random() {
+        let loads = ThreadLoads::new(TEST_NUM_THREADS);
+        let pk = Pubkey::new_unique();
+        let thread_set = TEST_ANY_THREADS - ThreadSet::only(1);
+        let selections = |seed| {
+            let mut selector = ThreadSelector::new(
+                ThreadSelectionStrategy::SeededRandom(seed),
+                TEST_NUM_THREADS,
+            );
+            (0..64)
+                .map(|_| selector.select(thread_set, &loads, &pk))
+                .collect::<Vec<_>>()
+        };
+
+        // same seed gives the same selections
+        let selections_0 = selections(0);
+        assert_eq!(selections_0, selections(0));
+        assert_ne!(selections_0, selections(1));
+
+        // only schedulable threads are selected, and all of them are used
+        assert!(selections_0.iter().all(|thread_id| *thread_id != 1));
+        for thread_id in [0, 2, 3] {
+            assert!(selections_0.contains(&thread_id));
+        }
+    }
+
+    #[test]
+    fn test_selection_metrics() {
+        let loads = ThreadLoads::new(TEST_NUM_THREADS);
+        let mut selector =
+            ThreadSelector::new(ThreadSelectionStrategy::RoundRobin, TEST_NUM_THREADS);
+        let pk = Pubkey::new_unique();
+        for _ in 0..2 * TEST_NUM_THREADS {
+            selector.select(TEST_ANY_THREADS, &loads, &pk);
+        }
+        assert_eq!(selector.metrics.num_selections, vec![2; TEST_NUM_THREADS]);
+
+        selector.report_metrics();
+        assert!(selector.metrics.num_selections.iter().all(|n| *n == 0));
+    }
+
+    #[test]
+    #[should_panic(expected = "removed transactions must be outstanding")]
+    fn test_thread_loads_remove_too_many() {
+        let mut loads = ThreadLoads::new(TEST_NUM_THREADS);
+        loads.add(0, 1, 100);
+        loads.remove(0, 2, 100);
+    }
+}
@@ -19,8 +19,8 @@ use {
     solana_measure::measure,
     solana_runtime::bank::Bank,
     solana_sdk::{
-        clock::FORWARD_TRANSACTIONS_TO_LEADER_AT_SLOT_OFFSET, feature_set::FeatureSet, hash::Hash,
-        saturating_add_assign, transaction::SanitizedTransaction,
+        clock::FORWARD_TRANSACTIONS_TO_LEADER_AT_SLOT_OFFSET, hash::Hash, saturating_add_assign,
+        transaction::SanitizedTransaction,
     },
     std::{
         collections::HashMap,
@@ -631,7 +631,7 @@ impl ThreadLocalUnprocessedPackets {
                                     &transaction_to_packet_indexes,
                                     &forwardable_transaction_indexes,
                                     &mut total_dropped_packets,
-                                    &bank.feature_set,
+                                    &bank,
                                 );
                             accepting_packets = accepted_packet_indexes.len()
                                 == forwardable_transaction_indexes.len();
@@ -781,7 +781,7 @@ impl ThreadLocalUnprocessedPackets {
         transaction_to_packet_indexes: &[usize],
         forwardable_transaction_indexes: &[usize],
         total_dropped_packets: &mut usize,
-        feature_set: &FeatureSet,
+        bank: &Bank,
     ) -> Vec<usize> {
         let mut added_packets_count: usize = 0;
         let mut accepted_packet_indexes = Vec::with_capacity(transaction_to_packet_indexes.len());
@@ -794,7 +794,8 @@ impl ThreadLocalUnprocessedPackets {
             if !forward_buffer.try_add_packet(
                 sanitized_transaction,
                 immutable_deserialized_packet,
-                feature_set,
+                &bank.feature_set,
+                bank.builtin_cost_table(),
             ) {
                 break;
             }
@@ -903,8 +904,8 @@ impl ThreadLocalUnprocessedPackets {
         reached_end_of_slot
     }
 
-    /// Prepare a chunk of packets for forwarding, filter out already forwarded packets while
-    /// counting tracers.
+    /// Prepare a chunk of packets for forwarding, filter out already forwarded and
+    /// non-forwardable packets while counting tracers.
     /// Returns Vec of unforwarded packets, and Vec<bool> of same size each indicates corresponding
     /// packet is tracer packet.
     fn prepare_packets_to_forward(
@@ -927,10 +928,14 @@ impl ThreadLocalUnprocessedPackets {
                 if is_tracer_packet {
                     saturating_add_assign!(*total_tracer_packets_in_buffer, 1);
                 }
-                if !self
-                    .unprocessed_packet_batches
-                    .is_forwarded(&immutable_deserialized_packet)
-                {
+                let is_forwardable = !immutable_deserialized_packet
+                    .original_packet()
+                    .meta()
+                    .non_forwardable()
+                    && !self
+                        .unprocessed_packet_batches
+                        .is_forwarded(&immutable_deserialized_packet);
+                if is_forwardable {
                     Some((immutable_deserialized_packet, is_tracer_packet))
                 } else {
                     forwarded_packets.push(immutable_deserialized_packet);
@@ -1318,6 +1323,33 @@ mod tests {
             assert_eq!(total_tracer_packets_to_forward, 256 - num_already_forwarded);
         }
 
+        // some packets are non-forwardable
+        {
+            let num_non_forwardable = 8;
+            let packets = simple_transactions
+                .iter()
+                .enumerate()
+                .map(|(packets_id, transaction)| {
+                    let mut p = Packet::from_data(None, transaction).unwrap();
+                    p.meta_mut().port = packets_id as u16;
+                    p.meta_mut().set_tracer(true);
+                    p.meta_mut()
+                        .set_non_forwardable(packets_id < num_non_forwardable);
+                    DeserializedPacket::new(p).unwrap()
+                })
+                .collect_vec();
+            let buffered_packet_batches: UnprocessedPacketBatches =
+                UnprocessedPacketBatches::from_iter(packets.clone().into_iter(), packets.len());
+            let (
+                total_tracer_packets_in_buffer,
+                total_packets_to_forward,
+                total_tracer_packets_to_forward,
+            ) = test_prepareing_buffered_packets_for_forwarding(buffered_packet_batches);
+            assert_eq!(total_tracer_packets_in_buffer, 256);
+            assert_eq!(total_packets_to_forward, 256 - num_non_forwardable);
+            assert_eq!(total_tracer_packets_to_forward, 256 - num_non_forwardable);
+        }
+
         // all packets are forwarded
         {
             for packet in &mut packets {
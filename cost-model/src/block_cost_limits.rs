@@ -81,3 +81,43 @@ static_assertions::const_assert_eq!(MAX_VOTE_UNITS, 36_000_000);
 /// The maximum allowed size, in bytes, that accounts data can grow, per block.
 /// This can also be thought of as the maximum size of new allocations per block.
 pub const MAX_BLOCK_ACCOUNTS_DATA_SIZE_DELTA: u64 = 100_000_000;
+
+/// Cost limits a `CostTracker` enforces on a block. Defaults to the
+/// cluster-wide limits above, and may be overridden, e.g. to exercise small
+/// limits in tests and benches.
+#[derive(AbiExample, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockCostLimits {
+    /// Number of compute units that a writable account in a block is allowed.
+    pub account_cost_limit: u64,
+    /// Number of compute units that a block is allowed.
+    pub block_cost_limit: u64,
+    /// Number of compute units that a block can have for vote transactions.
+    pub vote_cost_limit: u64,
+}
+
+impl Default for BlockCostLimits {
+    fn default() -> Self {
+        // Clippy doesn't like asserts in const contexts, so need to explicitly allow them.  For
+        // more info, see this issue: https://github.com/rust-lang/rust-clippy/issues/8159
+        #![allow(clippy::assertions_on_constants)]
+        const _: () = assert!(MAX_WRITABLE_ACCOUNT_UNITS <= MAX_BLOCK_UNITS);
+        const _: () = assert!(MAX_VOTE_UNITS <= MAX_BLOCK_UNITS);
+
+        Self {
+            account_cost_limit: MAX_WRITABLE_ACCOUNT_UNITS,
+            block_cost_limit: MAX_BLOCK_UNITS,
+            vote_cost_limit: MAX_VOTE_UNITS,
+        }
+    }
+}
+
+impl BlockCostLimits {
+    /// Returns the limits with each limit divided by `divisor`.
+    pub fn divided_by(&self, divisor: u64) -> Self {
+        Self {
+            account_cost_limit: self.account_cost_limit.saturating_div(divisor),
+            block_cost_limit: self.block_cost_limit.saturating_div(divisor),
+            vote_cost_limit: self.vote_cost_limit.saturating_div(divisor),
+        }
+    }
+}
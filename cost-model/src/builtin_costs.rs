@@ -0,0 +1,146 @@
+//! Table of builtin program instruction costs.
+//!
+//! The default costs live in `BUILT_IN_INSTRUCTION_COSTS`. The bank builds a
+//! `BuiltinCostTable` when the root bank is created at startup, which may add
+//! entries either for new builtins or to reprice existing ones, optionally
+//! gated by a feature so the new cost only applies once the feature is active.
+//! The table is immutable once built and shared by all descendant banks, so
+//! every holder of the same table prices transactions identically.
+//!
+use {
+    crate::block_cost_limits::BUILT_IN_INSTRUCTION_COSTS,
+    solana_sdk::{feature_set::FeatureSet, pubkey::Pubkey},
+    std::collections::HashMap,
+};
+
+/// Instruction cost entry for a builtin program.
+#[derive(AbiExample, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BuiltinCost {
+    pub program_id: Pubkey,
+    pub compute_units: u64,
+    /// Feature that must be active for this entry to apply. Entries without
+    /// a feature always apply.
+    pub feature_id: Option<Pubkey>,
+}
+
+/// Builtin instruction costs, on top of the default `BUILT_IN_INSTRUCTION_COSTS`.
+#[derive(AbiExample, Clone, Debug, Default)]
+pub struct BuiltinCostTable {
+    /// Entries by program id, in the order they were given.
+    builtin_costs: HashMap<Pubkey, Vec<BuiltinCost>>,
+}
+
+impl BuiltinCostTable {
+    /// Builds a table from `builtin_costs`. When several entries apply to the
+    /// same program, the last one is used. Entries take precedence over the
+    /// default `BUILT_IN_INSTRUCTION_COSTS`.
+    pub fn new(builtin_costs: impl IntoIterator<Item = BuiltinCost>) -> Self {
+        let mut table = HashMap::<Pubkey, Vec<BuiltinCost>>::new();
+        for builtin_cost in builtin_costs {
+            table
+                .entry(builtin_cost.program_id)
+                .or_default()
+                .push(builtin_cost);
+        }
+        Self {
+            builtin_costs: table,
+        }
+    }
+
+    /// Returns the instruction cost of the builtin program `program_id` under
+    /// `feature_set`, or `None` if `program_id` is not a builtin.
+    pub fn get_instruction_cost(
+        &self,
+        program_id: &Pubkey,
+        feature_set: &FeatureSet,
+    ) -> Option<u64> {
+        self.builtin_costs
+            .get(program_id)
+            .and_then(|builtin_costs| {
+                builtin_costs
+                    .iter()
+                    .rev()
+                    .find(|builtin_cost| {
+                        builtin_cost
+                            .feature_id
+                            .map_or(true, |feature_id| feature_set.is_active(&feature_id))
+                    })
+                    .map(|builtin_cost| builtin_cost.compute_units)
+            })
+            .or_else(|| BUILT_IN_INSTRUCTION_COSTS.get(program_id).copied())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, solana_sdk::system_program};
+
+    #[test]
+    fn test_get_instruction_cost_default() {
+        let builtin_cost_table = BuiltinCostTable::default();
+        let feature_set = FeatureSet::all_enabled();
+        assert_eq!(
+            builtin_cost_table.get_instruction_cost(&system_program::id(), &feature_set),
+            BUILT_IN_INSTRUCTION_COSTS
+                .get(&system_program::id())
+                .copied()
+        );
+        assert_eq!(
+            builtin_cost_table.get_instruction_cost(&Pubkey::new_unique(), &feature_set),
+            None
+        );
+    }
+
+    #[test]
+    fn test_get_instruction_cost() {
+        let program_id = Pubkey::new_unique();
+        let feature_id = Pubkey::new_unique();
+        let mut feature_set = FeatureSet::default();
+
+        // New builtin that always applies, repriced once the feature is active.
+        let builtin_cost_table = BuiltinCostTable::new([
+            BuiltinCost {
+                program_id,
+                compute_units: 100,
+                feature_id: None,
+            },
+            BuiltinCost {
+                program_id,
+                compute_units: 200,
+                feature_id: Some(feature_id),
+            },
+        ]);
+        assert_eq!(
+            builtin_cost_table.get_instruction_cost(&program_id, &feature_set),
+            Some(100)
+        );
+        feature_set.activate(&feature_id, 0);
+        assert_eq!(
+            builtin_cost_table.get_instruction_cost(&program_id, &feature_set),
+            Some(200)
+        );
+    }
+
+    #[test]
+    fn test_get_feature_gated_instruction_cost() {
+        let program_id = Pubkey::new_unique();
+        let feature_id = Pubkey::new_unique();
+        let mut feature_set = FeatureSet::default();
+
+        // New builtin behind a feature is not a builtin until activated.
+        let builtin_cost_table = BuiltinCostTable::new([BuiltinCost {
+            program_id,
+            compute_units: 300,
+            feature_id: Some(feature_id),
+        }]);
+        assert_eq!(
+            builtin_cost_table.get_instruction_cost(&program_id, &feature_set),
+            None
+        );
+        feature_set.activate(&feature_id, 0);
+        assert_eq!(
+            builtin_cost_table.get_instruction_cost(&program_id, &feature_set),
+            Some(300)
+        );
+    }
+}
@@ -6,7 +6,9 @@
 //!
 
 use {
-    crate::{block_cost_limits::*, transaction_cost::TransactionCost},
+    crate::{
+        block_cost_limits::*, builtin_costs::BuiltinCostTable, transaction_cost::TransactionCost,
+    },
     log::*,
     solana_program_runtime::compute_budget::{
         ComputeBudget, DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT,
@@ -34,12 +36,25 @@ impl CostModel {
     pub fn calculate_cost(
         transaction: &SanitizedTransaction,
         feature_set: &FeatureSet,
+    ) -> TransactionCost {
+        Self::calculate_cost_with_builtin_cost_table(
+            transaction,
+            feature_set,
+            &BuiltinCostTable::default(),
+        )
+    }
+
+    /// Like `calculate_cost`, pricing builtin instructions with `builtin_cost_table`.
+    pub fn calculate_cost_with_builtin_cost_table(
+        transaction: &SanitizedTransaction,
+        feature_set: &FeatureSet,
+        builtin_cost_table: &BuiltinCostTable,
     ) -> TransactionCost {
         let mut tx_cost = TransactionCost::new_with_default_capacity();
 
         tx_cost.signature_cost = Self::get_signature_cost(transaction);
         Self::get_write_lock_cost(&mut tx_cost, transaction);
-        Self::get_transaction_cost(&mut tx_cost, transaction, feature_set);
+        Self::get_transaction_cost(&mut tx_cost, transaction, feature_set, builtin_cost_table);
         tx_cost.account_data_size = Self::calculate_account_data_size(transaction);
         tx_cost.is_simple_vote = transaction.is_simple_vote_transaction();
 
@@ -87,18 +102,25 @@ impl CostModel {
         tx_cost: &mut TransactionCost,
         transaction: &SanitizedTransaction,
         feature_set: &FeatureSet,
+        builtin_cost_table: &BuiltinCostTable,
     ) {
         let mut builtin_costs = 0u64;
         let mut bpf_costs = 0u64;
         let mut loaded_accounts_data_size_cost = 0u64;
         let mut data_bytes_len_total = 0u64;
+        let mut bpf_program_id = None;
+        let mut builtin_program_costs = Vec::new();
 
         for (program_id, instruction) in transaction.message().program_instructions_iter() {
             // to keep the same behavior, look for builtin first
-            if let Some(builtin_cost) = BUILT_IN_INSTRUCTION_COSTS.get(program_id) {
-                builtin_costs = builtin_costs.saturating_add(*builtin_cost);
+            if let Some(builtin_cost) =
+                builtin_cost_table.get_instruction_cost(program_id, feature_set)
+            {
+                builtin_costs = builtin_costs.saturating_add(builtin_cost);
+                builtin_program_costs.push((*program_id, builtin_cost));
             } else {
                 bpf_costs = bpf_costs.saturating_add(DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT.into());
+                bpf_program_id.get_or_insert(*program_id);
             }
             data_bytes_len_total =
                 data_bytes_len_total.saturating_add(instruction.data.len() as u64);
@@ -140,11 +162,14 @@ impl CostModel {
             Err(_) => {
                 builtin_costs = 0;
                 bpf_costs = 0;
+                builtin_program_costs.clear();
             }
         }
 
         tx_cost.builtins_execution_cost = builtin_costs;
         tx_cost.bpf_execution_cost = bpf_costs;
+        tx_cost.bpf_program_id = bpf_program_id;
+        tx_cost.builtin_program_costs = builtin_program_costs;
         tx_cost.loaded_accounts_data_size_cost = loaded_accounts_data_size_cost;
         tx_cost.data_bytes_cost = data_bytes_len_total / INSTRUCTION_DATA_BYTES_COST;
     }
@@ -207,6 +232,7 @@ impl CostModel {
 mod tests {
     use {
         super::*,
+        crate::builtin_costs::BuiltinCost,
         solana_sdk::{
             compute_budget::{self, ComputeBudgetInstruction},
             hash::Hash,
@@ -294,10 +320,16 @@ mod tests {
             &mut tx_cost,
             &simple_transaction,
             &FeatureSet::all_enabled(),
+            &BuiltinCostTable::default(),
         );
         assert_eq!(*expected_execution_cost, tx_cost.builtins_execution_cost);
         assert_eq!(0, tx_cost.bpf_execution_cost);
         assert_eq!(3, tx_cost.data_bytes_cost);
+        assert_eq!(None, tx_cost.bpf_program_id);
+        assert_eq!(
+            vec![(system_program::id(), *expected_execution_cost)],
+            tx_cost.builtin_program_costs
+        );
     }
 
     #[test]
@@ -323,6 +355,7 @@ mod tests {
             &mut tx_cost,
             &token_transaction,
             &FeatureSet::all_enabled(),
+            &BuiltinCostTable::default(),
         );
         assert_eq!(0, tx_cost.builtins_execution_cost);
         assert_eq!(200_000, tx_cost.bpf_execution_cost);
@@ -360,6 +393,7 @@ mod tests {
             &mut tx_cost,
             &token_transaction,
             &FeatureSet::all_enabled(),
+            &BuiltinCostTable::default(),
         );
         assert_eq!(
             *BUILT_IN_INSTRUCTION_COSTS
@@ -410,9 +444,11 @@ mod tests {
             &mut tx_cost,
             &token_transaction,
             &FeatureSet::all_enabled(),
+            &BuiltinCostTable::default(),
         );
         assert_eq!(0, tx_cost.builtins_execution_cost);
         assert_eq!(0, tx_cost.bpf_execution_cost);
+        assert!(tx_cost.builtin_program_costs.is_empty());
     }
 
     #[test]
@@ -438,7 +474,12 @@ mod tests {
         let expected_cost = program_cost * 2;
 
         let mut tx_cost = TransactionCost::default();
-        CostModel::get_transaction_cost(&mut tx_cost, &tx, &FeatureSet::all_enabled());
+        CostModel::get_transaction_cost(
+            &mut tx_cost,
+            &tx,
+            &FeatureSet::all_enabled(),
+            &BuiltinCostTable::default(),
+        );
         assert_eq!(expected_cost, tx_cost.builtins_execution_cost);
         assert_eq!(0, tx_cost.bpf_execution_cost);
         assert_eq!(6, tx_cost.data_bytes_cost);
@@ -470,10 +511,21 @@ mod tests {
 
         let expected_cost = DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT as u64 * 2;
         let mut tx_cost = TransactionCost::default();
-        CostModel::get_transaction_cost(&mut tx_cost, &tx, &FeatureSet::all_enabled());
+        CostModel::get_transaction_cost(
+            &mut tx_cost,
+            &tx,
+            &FeatureSet::all_enabled(),
+            &BuiltinCostTable::default(),
+        );
         assert_eq!(0, tx_cost.builtins_execution_cost);
         assert_eq!(expected_cost, tx_cost.bpf_execution_cost);
         assert_eq!(0, tx_cost.data_bytes_cost);
+        assert_eq!(Some(prog1), tx_cost.bpf_program_id);
+        assert!(tx_cost.builtin_program_costs.is_empty());
+        assert_eq!(
+            vec![(prog1, expected_cost)],
+            tx_cost.program_execution_costs().collect::<Vec<_>>()
+        );
     }
 
     #[test]
@@ -539,6 +591,46 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_cost_model_calculate_cost_with_builtin_cost_table() {
+        let (mint_keypair, start_hash) = test_setup();
+        let tx = SanitizedTransaction::from_transaction_for_tests(system_transaction::transfer(
+            &mint_keypair,
+            &Keypair::new().pubkey(),
+            2,
+            start_hash,
+        ));
+
+        // reprice the system program, only once the feature is active
+        let feature_id = Pubkey::new_unique();
+        let builtin_cost_table = BuiltinCostTable::new([BuiltinCost {
+            program_id: system_program::id(),
+            compute_units: 1_000,
+            feature_id: Some(feature_id),
+        }]);
+        let mut feature_set = FeatureSet::all_enabled();
+        let tx_cost = CostModel::calculate_cost_with_builtin_cost_table(
+            &tx,
+            &feature_set,
+            &builtin_cost_table,
+        );
+        assert_eq!(
+            *BUILT_IN_INSTRUCTION_COSTS
+                .get(&system_program::id())
+                .unwrap(),
+            tx_cost.builtins_execution_cost
+        );
+
+        feature_set.activate(&feature_id, 0);
+        let tx_cost = CostModel::calculate_cost_with_builtin_cost_table(
+            &tx,
+            &feature_set,
+            &builtin_cost_table,
+        );
+        assert_eq!(1_000, tx_cost.builtins_execution_cost);
+        assert_eq!(0, tx_cost.bpf_execution_cost);
+    }
+
     #[test]
     fn test_cost_model_calculate_cost_disabled_feature() {
         let (mint_keypair, start_hash) = test_setup();
@@ -9,10 +9,19 @@ use {
     solana_sdk::{
         clock::Slot, pubkey::Pubkey, saturating_add_assign, transaction::TransactionError,
     },
-    std::{cmp::Ordering, collections::HashMap},
+    std::{
+        cmp::{self, Ordering},
+        collections::{hash_map::Entry, HashMap},
+    },
 };
 
 const WRITABLE_ACCOUNTS_PER_BLOCK: usize = 512;
+/// Maximum number of programs whose execution cost estimate errors are reported per block.
+const MAX_REPORTED_EXECUTION_COST_ESTIMATES: usize = 10;
+/// Maximum number of programs whose execution cost estimates are tracked per block.
+const MAX_TRACKED_EXECUTION_COST_ESTIMATES: usize = 256;
+/// Maximum number of programs whose costs are reported per block.
+const MAX_REPORTED_PROGRAM_COSTS: usize = 10;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CostTrackerError {
@@ -48,11 +57,63 @@ impl From<CostTrackerError> for TransactionError {
     }
 }
 
+/// Costs reserved in a `CostTracker` for a batch of transactions, so they can
+/// be committed or rolled back together.
+#[must_use]
+#[derive(Debug, Default)]
+pub struct CostReservation {
+    cost_by_writable_accounts: HashMap<Pubkey, u64>,
+    cost_by_program: HashMap<Pubkey, u64>,
+    block_cost: u64,
+    vote_cost: u64,
+    fixed_cost: u64,
+    transaction_count: u64,
+    account_data_size: u64,
+}
+
+impl CostReservation {
+    pub fn transaction_count(&self) -> u64 {
+        self.transaction_count
+    }
+
+    pub fn block_cost(&self) -> u64 {
+        self.block_cost
+    }
+}
+
+/// What happened to a transaction whose cost was reserved, applied to the
+/// tracker when the reservation is committed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReservedTransactionOutcome {
+    /// The transaction was not committed; its cost is removed.
+    NotCommitted,
+    /// The transaction was committed; its estimated cost is kept.
+    Committed,
+    /// The transaction was committed; its execution cost is adjusted from the
+    /// estimate to the actual executed units.
+    CommittedWithExecutionUnits(u64),
+}
+
+/// Accumulated estimated and actual execution units of the committed
+/// transactions invoking a program.
+#[derive(AbiExample, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ExecutionCostEstimate {
+    pub transaction_count: u64,
+    pub estimated_units: u64,
+    pub actual_units: u64,
+}
+
+impl ExecutionCostEstimate {
+    /// Number of units estimated in excess of the actual units, negative if
+    /// the program was under-estimated.
+    pub fn estimate_error(&self) -> i64 {
+        (self.estimated_units as i64).saturating_sub(self.actual_units as i64)
+    }
+}
+
 #[derive(AbiExample, Debug)]
 pub struct CostTracker {
-    account_cost_limit: u64,
-    block_cost_limit: u64,
-    vote_cost_limit: u64,
+    block_cost_limits: BlockCostLimits,
     cost_by_writable_accounts: HashMap<Pubkey, u64>,
     block_cost: u64,
     vote_cost: u64,
@@ -62,26 +123,43 @@ pub struct CostTracker {
     /// The amount of total account data size remaining.  If `Some`, then do not add transactions
     /// that would cause `account_data_size` to exceed this limit.
     account_data_size_limit: Option<u64>,
+
+    /// Estimated and actual execution units of committed transactions, by
+    /// the program of their first non-builtin instruction. Only the actual
+    /// units of the whole transaction are known, so they are all attributed
+    /// to that program, even if other instructions used some of them.
+    /// Transactions invoking only builtins are not tracked. Bounded by
+    /// `MAX_TRACKED_EXECUTION_COST_ESTIMATES` programs per block.
+    execution_cost_estimates: HashMap<Pubkey, ExecutionCostEstimate>,
+
+    /// Execution costs of transactions in the block, by program. See
+    /// `TransactionCost::program_execution_costs`.
+    cost_by_program: HashMap<Pubkey, u64>,
+
+    /// Costs of transactions in the block that do not depend on execution.
+    fixed_cost: u64,
+
+    /// Block capacity held back for simple vote transactions. Transactions
+    /// other than simple votes may not use the part of it that votes have
+    /// not used yet. Zero by default, since it is a block production policy
+    /// and must not apply when replaying blocks.
+    vote_cost_reservation: u64,
 }
 
 impl Default for CostTracker {
     fn default() -> Self {
-        // Clippy doesn't like asserts in const contexts, so need to explicitly allow them.  For
-        // more info, see this issue: https://github.com/rust-lang/rust-clippy/issues/8159
-        #![allow(clippy::assertions_on_constants)]
-        const _: () = assert!(MAX_WRITABLE_ACCOUNT_UNITS <= MAX_BLOCK_UNITS);
-        const _: () = assert!(MAX_VOTE_UNITS <= MAX_BLOCK_UNITS);
-
         Self {
-            account_cost_limit: MAX_WRITABLE_ACCOUNT_UNITS,
-            block_cost_limit: MAX_BLOCK_UNITS,
-            vote_cost_limit: MAX_VOTE_UNITS,
+            block_cost_limits: BlockCostLimits::default(),
             cost_by_writable_accounts: HashMap::with_capacity(WRITABLE_ACCOUNTS_PER_BLOCK),
             block_cost: 0,
             vote_cost: 0,
             transaction_count: 0,
             account_data_size: 0,
             account_data_size_limit: None,
+            execution_cost_estimates: HashMap::new(),
+            cost_by_program: HashMap::new(),
+            fixed_cost: 0,
+            vote_cost_reservation: 0,
         }
     }
 }
@@ -96,6 +174,20 @@ impl CostTracker {
         }
     }
 
+    /// Construct a new CostTracker with the given block cost limits and
+    /// account data size limit.
+    #[must_use]
+    pub fn new_with_block_cost_limits(
+        block_cost_limits: BlockCostLimits,
+        account_data_size_limit: Option<u64>,
+    ) -> Self {
+        Self {
+            block_cost_limits,
+            account_data_size_limit,
+            ..Self::default()
+        }
+    }
+
     /// allows to adjust limits initiated during construction
     pub fn set_limits(
         &mut self,
@@ -103,9 +195,35 @@ impl CostTracker {
         block_cost_limit: u64,
         vote_cost_limit: u64,
     ) {
-        self.account_cost_limit = account_cost_limit;
-        self.block_cost_limit = block_cost_limit;
-        self.vote_cost_limit = vote_cost_limit;
+        self.set_block_cost_limits(BlockCostLimits {
+            account_cost_limit,
+            block_cost_limit,
+            vote_cost_limit,
+        });
+    }
+
+    /// allows to replace the block cost limits initiated during construction
+    pub fn set_block_cost_limits(&mut self, block_cost_limits: BlockCostLimits) {
+        self.block_cost_limits = block_cost_limits;
+    }
+
+    pub fn block_cost_limits(&self) -> &BlockCostLimits {
+        &self.block_cost_limits
+    }
+
+    /// Reserves up to `vote_cost_reservation` units of the block for simple
+    /// vote transactions, capped at the vote cost limit, so votes still fit
+    /// once other transactions have filled the rest of the block.
+    pub fn set_vote_cost_reservation(&mut self, vote_cost_reservation: u64) {
+        self.vote_cost_reservation = vote_cost_reservation;
+    }
+
+    /// Returns the part of the vote cost reservation not yet used by simple
+    /// vote transactions.
+    fn unused_vote_cost_reservation(&self) -> u64 {
+        self.vote_cost_reservation
+            .min(self.block_cost_limits.vote_cost_limit)
+            .saturating_sub(self.vote_cost)
     }
 
     pub fn try_add(&mut self, tx_cost: &TransactionCost) -> Result<u64, CostTrackerError> {
@@ -114,12 +232,107 @@ impl CostTracker {
         Ok(self.block_cost)
     }
 
+    /// Adds `tx_cost` to the tracker like `try_add`, and records it in
+    /// `reservation` so it can later be rolled back with the rest of the batch.
+    pub fn try_reserve(
+        &mut self,
+        reservation: &mut CostReservation,
+        tx_cost: &TransactionCost,
+    ) -> Result<u64, CostTrackerError> {
+        let block_cost = self.try_add(tx_cost)?;
+
+        let cost = tx_cost.sum();
+        for account_key in tx_cost.writable_accounts.iter() {
+            let account_cost = reservation
+                .cost_by_writable_accounts
+                .entry(*account_key)
+                .or_insert(0);
+            *account_cost = account_cost.saturating_add(cost);
+        }
+        for (program_id, execution_cost) in tx_cost.program_execution_costs() {
+            let program_cost = reservation.cost_by_program.entry(program_id).or_insert(0);
+            *program_cost = program_cost.saturating_add(execution_cost);
+        }
+        saturating_add_assign!(reservation.block_cost, cost);
+        if tx_cost.is_simple_vote {
+            saturating_add_assign!(reservation.vote_cost, cost);
+        }
+        saturating_add_assign!(reservation.fixed_cost, tx_cost.fixed_cost());
+        saturating_add_assign!(reservation.transaction_count, 1);
+        saturating_add_assign!(reservation.account_data_size, tx_cost.account_data_size);
+
+        Ok(block_cost)
+    }
+
+    /// Keeps the reserved costs of a batch that was successfully recorded,
+    /// and applies the `outcomes` of its transactions: the cost of each
+    /// transaction that was not committed is removed, and committed
+    /// transactions may have their execution cost adjusted to the actual
+    /// units. Consumes `reservation`, so it can no longer be rolled back once
+    /// its costs have been adjusted.
+    pub fn commit_reservation<'a>(
+        &mut self,
+        reservation: CostReservation,
+        outcomes: impl IntoIterator<Item = (&'a TransactionCost, ReservedTransactionOutcome)>,
+    ) {
+        drop(reservation);
+        for (tx_cost, outcome) in outcomes {
+            match outcome {
+                ReservedTransactionOutcome::NotCommitted => self.remove(tx_cost),
+                ReservedTransactionOutcome::Committed => (),
+                ReservedTransactionOutcome::CommittedWithExecutionUnits(actual_execution_units) => {
+                    self.update_execution_cost(tx_cost, actual_execution_units)
+                }
+            }
+        }
+    }
+
+    /// Removes all reserved costs of an aborted batch in one pass. The costs
+    /// of reserved transactions are only adjusted by `commit_reservation`,
+    /// which consumes the reservation, so they are removed exactly as they
+    /// were added by `try_reserve`.
+    pub fn rollback_reservation(&mut self, reservation: CostReservation) {
+        for (account_key, cost) in reservation.cost_by_writable_accounts {
+            if let Some(account_cost) = self.cost_by_writable_accounts.get_mut(&account_key) {
+                *account_cost = account_cost.saturating_sub(cost);
+            }
+        }
+        for (program_id, cost) in reservation.cost_by_program {
+            if let Some(program_cost) = self.cost_by_program.get_mut(&program_id) {
+                *program_cost = program_cost.saturating_sub(cost);
+            }
+        }
+        self.block_cost = self.block_cost.saturating_sub(reservation.block_cost);
+        self.vote_cost = self.vote_cost.saturating_sub(reservation.vote_cost);
+        self.fixed_cost = self.fixed_cost.saturating_sub(reservation.fixed_cost);
+        self.transaction_count = self
+            .transaction_count
+            .saturating_sub(reservation.transaction_count);
+        self.account_data_size = self
+            .account_data_size
+            .saturating_sub(reservation.account_data_size);
+    }
+
+    /// Adjusts the block cost accounting of a committed transaction from its
+    /// estimated execution units to `actual_execution_units`, releasing any
+    /// over-reserved units for the rest of the block.
+    ///
+    /// The estimate error is recorded against the program of the
+    /// transaction's first non-builtin instruction, see
+    /// `execution_cost_estimate`.
     pub fn update_execution_cost(
         &mut self,
         estimated_tx_cost: &TransactionCost,
         actual_execution_units: u64,
     ) {
         let estimated_execution_units = estimated_tx_cost.bpf_execution_cost;
+        if let Some(program_id) = estimated_tx_cost.bpf_program_id {
+            self.record_execution_cost_estimate(
+                program_id,
+                estimated_execution_units,
+                actual_execution_units,
+            );
+        }
         match actual_execution_units.cmp(&estimated_execution_units) {
             Ordering::Equal => (),
             Ordering::Greater => {
@@ -149,6 +362,94 @@ impl CostTracker {
         self.transaction_count
     }
 
+    /// Returns the compute units used by simple vote transactions in this block.
+    pub fn vote_cost(&self) -> u64 {
+        self.vote_cost
+    }
+
+    /// Returns the compute units used by transactions other than simple votes
+    /// in this block.
+    pub fn non_vote_cost(&self) -> u64 {
+        self.block_cost.saturating_sub(self.vote_cost)
+    }
+
+    /// Returns the compute units of the block used by signatures, write
+    /// locks, instruction data and loaded accounts data, as opposed to
+    /// execution.
+    pub fn fixed_cost(&self) -> u64 {
+        self.fixed_cost
+    }
+
+    /// Returns the number of compute units simple vote transactions can still
+    /// use in this block, bounded by both the vote and the block limits.
+    pub fn remaining_vote_cus(&self) -> u64 {
+        let remaining_vote_cus = self
+            .block_cost_limits
+            .vote_cost_limit
+            .saturating_sub(self.vote_cost);
+        let remaining_block_cus = self
+            .block_cost_limits
+            .block_cost_limit
+            .saturating_sub(self.block_cost);
+        remaining_vote_cus.min(remaining_block_cus)
+    }
+
+    /// Returns the number of compute units `account` can still be charged in
+    /// this block before reaching the per-account limit.
+    pub fn account_remaining_cus(&self, account: &Pubkey) -> u64 {
+        let account_cost = self
+            .cost_by_writable_accounts
+            .get(account)
+            .copied()
+            .unwrap_or_default();
+        self.block_cost_limits
+            .account_cost_limit
+            .saturating_sub(account_cost)
+    }
+
+    /// Returns up to `n` writable accounts with the highest accumulated costs
+    /// in this block, sorted by cost in descending order.
+    pub fn top_n_costly_accounts(&self, n: usize) -> Vec<(Pubkey, u64)> {
+        if n == 0 {
+            return Vec::new();
+        }
+        let mut accounts: Vec<_> = self
+            .cost_by_writable_accounts
+            .iter()
+            .filter(|(_, cost)| **cost > 0)
+            .map(|(account, cost)| (*account, *cost))
+            .collect();
+        let compare = |(account_a, cost_a): &(Pubkey, u64), (account_b, cost_b): &(Pubkey, u64)| {
+            cost_b.cmp(cost_a).then_with(|| account_a.cmp(account_b))
+        };
+        // only the top `n` accounts need to be sorted
+        if n < accounts.len() {
+            accounts.select_nth_unstable_by(n - 1, compare);
+            accounts.truncate(n);
+        }
+        accounts.sort_unstable_by(compare);
+        accounts
+    }
+
+    /// Returns the execution cost of `program_id` in the block, including
+    /// adjustments to the actual executed units.
+    pub fn program_execution_cost(&self, program_id: &Pubkey) -> u64 {
+        self.cost_by_program
+            .get(program_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Returns the accumulated execution cost estimate of committed
+    /// transactions whose first non-builtin instruction invokes `program_id`.
+    /// This is an approximation: the actual units of the whole transaction,
+    /// including its builtin and later instructions, count towards
+    /// `program_id`. Returns `None` for programs that were not tracked,
+    /// including builtins.
+    pub fn execution_cost_estimate(&self, program_id: &Pubkey) -> Option<&ExecutionCostEstimate> {
+        self.execution_cost_estimates.get(program_id)
+    }
+
     pub fn report_stats(&self, bank_slot: Slot) {
         // skip reporting if block is empty
         if self.transaction_count == 0 {
@@ -162,12 +463,86 @@ impl CostTracker {
             ("bank_slot", bank_slot as i64, i64),
             ("block_cost", self.block_cost as i64, i64),
             ("vote_cost", self.vote_cost as i64, i64),
+            ("fixed_cost", self.fixed_cost as i64, i64),
+            (
+                "execution_cost",
+                self.block_cost.saturating_sub(self.fixed_cost) as i64,
+                i64
+            ),
             ("transaction_count", self.transaction_count as i64, i64),
             ("number_of_accounts", self.number_of_accounts() as i64, i64),
             ("costliest_account", costliest_account.to_string(), String),
             ("costliest_account_cost", costliest_account_cost as i64, i64),
             ("account_data_size", self.account_data_size, i64),
         );
+
+        for (program_id, cost) in self.costliest_programs() {
+            datapoint_info!(
+                "cost_tracker_program_cost",
+                ("bank_slot", bank_slot as i64, i64),
+                ("program_id", program_id.to_string(), String),
+                ("execution_cost", cost as i64, i64),
+            );
+        }
+
+        for (program_id, estimate) in self.worst_execution_cost_estimates() {
+            datapoint_info!(
+                "cost_tracker_execution_cost_estimate",
+                ("bank_slot", bank_slot as i64, i64),
+                ("program_id", program_id.to_string(), String),
+                ("transaction_count", estimate.transaction_count as i64, i64),
+                ("estimated_units", estimate.estimated_units as i64, i64),
+                ("actual_units", estimate.actual_units as i64, i64),
+                ("estimate_error", estimate.estimate_error(), i64),
+            );
+        }
+    }
+
+    /// Returns the programs with the highest execution costs in the block, up
+    /// to `MAX_REPORTED_PROGRAM_COSTS`, sorted by cost in descending order.
+    fn costliest_programs(&self) -> Vec<(Pubkey, u64)> {
+        let mut program_costs: Vec<_> = self
+            .cost_by_program
+            .iter()
+            .filter(|(_, cost)| **cost > 0)
+            .map(|(program_id, cost)| (*program_id, *cost))
+            .collect();
+        program_costs.sort_unstable_by_key(|(program_id, cost)| (cmp::Reverse(*cost), *program_id));
+        program_costs.truncate(MAX_REPORTED_PROGRAM_COSTS);
+        program_costs
+    }
+
+    /// Returns the programs with the largest absolute execution cost estimate
+    /// errors, up to `MAX_REPORTED_EXECUTION_COST_ESTIMATES`.
+    fn worst_execution_cost_estimates(&self) -> Vec<(&Pubkey, &ExecutionCostEstimate)> {
+        let mut estimates: Vec<_> = self.execution_cost_estimates.iter().collect();
+        estimates.sort_unstable_by_key(|(_, estimate)| {
+            cmp::Reverse(estimate.estimate_error().unsigned_abs())
+        });
+        estimates.truncate(MAX_REPORTED_EXECUTION_COST_ESTIMATES);
+        estimates
+    }
+
+    /// Accumulates the estimated and actual execution units of a committed
+    /// transaction into the estimate of `program_id`, unless the maximum
+    /// number of programs are already tracked in this block.
+    fn record_execution_cost_estimate(
+        &mut self,
+        program_id: Pubkey,
+        estimated_units: u64,
+        actual_units: u64,
+    ) {
+        let num_tracked_programs = self.execution_cost_estimates.len();
+        let estimate = match self.execution_cost_estimates.entry(program_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) if num_tracked_programs < MAX_TRACKED_EXECUTION_COST_ESTIMATES => {
+                entry.insert(ExecutionCostEstimate::default())
+            }
+            Entry::Vacant(_) => return,
+        };
+        saturating_add_assign!(estimate.transaction_count, 1);
+        saturating_add_assign!(estimate.estimated_units, estimated_units);
+        saturating_add_assign!(estimate.actual_units, actual_units);
     }
 
     fn find_costliest_account(&self) -> (Pubkey, u64) {
@@ -182,18 +557,26 @@ impl CostTracker {
         let cost: u64 = tx_cost.sum();
         let vote_cost = if tx_cost.is_simple_vote { cost } else { 0 };
 
-        // check against the total package cost
-        if self.block_cost.saturating_add(cost) > self.block_cost_limit {
+        // check against the total package cost, leaving the unused vote
+        // reservation to simple vote transactions
+        let block_cost_limit = if tx_cost.is_simple_vote {
+            self.block_cost_limits.block_cost_limit
+        } else {
+            self.block_cost_limits
+                .block_cost_limit
+                .saturating_sub(self.unused_vote_cost_reservation())
+        };
+        if self.block_cost.saturating_add(cost) > block_cost_limit {
             return Err(CostTrackerError::WouldExceedBlockMaxLimit);
         }
 
         // if vote transaction, check if it exceeds vote_transaction_limit
-        if self.vote_cost.saturating_add(vote_cost) > self.vote_cost_limit {
+        if self.vote_cost.saturating_add(vote_cost) > self.block_cost_limits.vote_cost_limit {
             return Err(CostTrackerError::WouldExceedVoteMaxLimit);
         }
 
         // check if the transaction itself is more costly than the account_cost_limit
-        if cost > self.account_cost_limit {
+        if cost > self.block_cost_limits.account_cost_limit {
             return Err(CostTrackerError::WouldExceedAccountMaxLimit);
         }
 
@@ -216,7 +599,8 @@ impl CostTracker {
         for account_key in tx_cost.writable_accounts.iter() {
             match self.cost_by_writable_accounts.get(account_key) {
                 Some(chained_cost) => {
-                    if chained_cost.saturating_add(cost) > self.account_cost_limit {
+                    if chained_cost.saturating_add(cost) > self.block_cost_limits.account_cost_limit
+                    {
                         return Err(CostTrackerError::WouldExceedAccountMaxLimit);
                     } else {
                         continue;
@@ -230,14 +614,24 @@ impl CostTracker {
     }
 
     fn add_transaction_cost(&mut self, tx_cost: &TransactionCost) {
-        self.add_transaction_execution_cost(tx_cost, tx_cost.sum());
+        self.add_transaction_block_cost(tx_cost, tx_cost.sum());
+        for (program_id, execution_cost) in tx_cost.program_execution_costs() {
+            let program_cost = self.cost_by_program.entry(program_id).or_insert(0);
+            *program_cost = program_cost.saturating_add(execution_cost);
+        }
+        saturating_add_assign!(self.fixed_cost, tx_cost.fixed_cost());
         saturating_add_assign!(self.account_data_size, tx_cost.account_data_size);
         saturating_add_assign!(self.transaction_count, 1);
     }
 
     fn remove_transaction_cost(&mut self, tx_cost: &TransactionCost) {
-        let cost = tx_cost.sum();
-        self.sub_transaction_execution_cost(tx_cost, cost);
+        self.sub_transaction_block_cost(tx_cost, tx_cost.sum());
+        for (program_id, execution_cost) in tx_cost.program_execution_costs() {
+            if let Some(program_cost) = self.cost_by_program.get_mut(&program_id) {
+                *program_cost = program_cost.saturating_sub(execution_cost);
+            }
+        }
+        self.fixed_cost = self.fixed_cost.saturating_sub(tx_cost.fixed_cost());
         self.account_data_size = self
             .account_data_size
             .saturating_sub(tx_cost.account_data_size);
@@ -246,31 +640,51 @@ impl CostTracker {
 
     /// Apply additional actual execution units to cost_tracker
     fn add_transaction_execution_cost(&mut self, tx_cost: &TransactionCost, adjustment: u64) {
+        self.add_transaction_block_cost(tx_cost, adjustment);
+        if let Some(program_id) = tx_cost.execution_cost_program_id() {
+            let program_cost = self.cost_by_program.entry(program_id).or_insert(0);
+            *program_cost = program_cost.saturating_add(adjustment);
+        }
+    }
+
+    /// Subtract extra execution units from cost_tracker
+    fn sub_transaction_execution_cost(&mut self, tx_cost: &TransactionCost, adjustment: u64) {
+        self.sub_transaction_block_cost(tx_cost, adjustment);
+        if let Some(program_cost) = tx_cost
+            .execution_cost_program_id()
+            .and_then(|program_id| self.cost_by_program.get_mut(&program_id))
+        {
+            *program_cost = program_cost.saturating_sub(adjustment);
+        }
+    }
+
+    /// Charge `cost` to the writable accounts, block and vote costs
+    fn add_transaction_block_cost(&mut self, tx_cost: &TransactionCost, cost: u64) {
         for account_key in tx_cost.writable_accounts.iter() {
             let account_cost = self
                 .cost_by_writable_accounts
                 .entry(*account_key)
                 .or_insert(0);
-            *account_cost = account_cost.saturating_add(adjustment);
+            *account_cost = account_cost.saturating_add(cost);
         }
-        self.block_cost = self.block_cost.saturating_add(adjustment);
+        self.block_cost = self.block_cost.saturating_add(cost);
         if tx_cost.is_simple_vote {
-            self.vote_cost = self.vote_cost.saturating_add(adjustment);
+            self.vote_cost = self.vote_cost.saturating_add(cost);
         }
     }
 
-    /// Subtract extra execution units from cost_tracker
-    fn sub_transaction_execution_cost(&mut self, tx_cost: &TransactionCost, adjustment: u64) {
+    /// Release `cost` from the writable accounts, block and vote costs
+    fn sub_transaction_block_cost(&mut self, tx_cost: &TransactionCost, cost: u64) {
         for account_key in tx_cost.writable_accounts.iter() {
             let account_cost = self
                 .cost_by_writable_accounts
                 .entry(*account_key)
                 .or_insert(0);
-            *account_cost = account_cost.saturating_sub(adjustment);
+            *account_cost = account_cost.saturating_sub(cost);
         }
-        self.block_cost = self.block_cost.saturating_sub(adjustment);
+        self.block_cost = self.block_cost.saturating_sub(cost);
         if tx_cost.is_simple_vote {
-            self.vote_cost = self.vote_cost.saturating_sub(adjustment);
+            self.vote_cost = self.vote_cost.saturating_sub(cost);
         }
     }
 
@@ -309,9 +723,11 @@ mod tests {
             assert!(account_cost_limit <= block_cost_limit);
             assert!(vote_cost_limit <= block_cost_limit);
             Self {
-                account_cost_limit,
-                block_cost_limit,
-                vote_cost_limit,
+                block_cost_limits: BlockCostLimits {
+                    account_cost_limit,
+                    block_cost_limit,
+                    vote_cost_limit,
+                },
                 account_data_size_limit,
                 ..Self::default()
             }
@@ -370,13 +786,41 @@ mod tests {
     #[test]
     fn test_cost_tracker_initialization() {
         let testee = CostTracker::new(10, 11, 8, None);
-        assert_eq!(10, testee.account_cost_limit);
-        assert_eq!(11, testee.block_cost_limit);
-        assert_eq!(8, testee.vote_cost_limit);
+        assert_eq!(10, testee.block_cost_limits.account_cost_limit);
+        assert_eq!(11, testee.block_cost_limits.block_cost_limit);
+        assert_eq!(8, testee.block_cost_limits.vote_cost_limit);
         assert_eq!(0, testee.cost_by_writable_accounts.len());
         assert_eq!(0, testee.block_cost);
     }
 
+    #[test]
+    fn test_set_block_cost_limits() {
+        let mut testee = CostTracker::default();
+        assert_eq!(&BlockCostLimits::default(), testee.block_cost_limits());
+
+        let block_cost_limits = BlockCostLimits::default().divided_by(4);
+        assert_eq!(
+            BlockCostLimits {
+                account_cost_limit: MAX_WRITABLE_ACCOUNT_UNITS / 4,
+                block_cost_limit: MAX_BLOCK_UNITS / 4,
+                vote_cost_limit: MAX_VOTE_UNITS / 4,
+            },
+            block_cost_limits
+        );
+        testee.set_block_cost_limits(block_cost_limits);
+        assert_eq!(&block_cost_limits, testee.block_cost_limits());
+
+        testee.set_limits(10, 11, 8);
+        assert_eq!(
+            &BlockCostLimits {
+                account_cost_limit: 10,
+                block_cost_limit: 11,
+                vote_cost_limit: 8,
+            },
+            testee.block_cost_limits()
+        );
+    }
+
     #[test]
     fn test_cost_tracker_ok_add_one() {
         let (mint_keypair, start_hash) = test_setup();
@@ -409,6 +853,81 @@ mod tests {
         assert_eq!(cost, costliest_account_cost);
     }
 
+    #[test]
+    fn test_remaining_vote_cus() {
+        let (mint_keypair, start_hash) = test_setup();
+        let (_tx, vote_tx_cost) = build_simple_vote_transaction(&mint_keypair, &start_hash);
+        let vote_cost = vote_tx_cost.sum();
+        let user_tx_cost = TransactionCost {
+            writable_accounts: vec![Pubkey::new_unique()],
+            bpf_execution_cost: vote_cost,
+            ..TransactionCost::default()
+        };
+
+        let mut testee = CostTracker::new(vote_cost * 3, vote_cost * 4, vote_cost * 2, None);
+        assert_eq!(vote_cost * 2, testee.remaining_vote_cus());
+
+        // vote usage is tracked separately from user transactions
+        assert!(testee.try_add(&vote_tx_cost).is_ok());
+        assert!(testee.try_add(&user_tx_cost).is_ok());
+        assert_eq!(vote_cost, testee.vote_cost());
+        assert_eq!(vote_cost, testee.non_vote_cost());
+        assert_eq!(vote_cost, testee.remaining_vote_cus());
+
+        // remaining vote capacity is also bounded by the block limit
+        assert!(testee.try_add(&user_tx_cost).is_ok());
+        assert!(testee.try_add(&user_tx_cost).is_ok());
+        assert_eq!(0, testee.remaining_vote_cus());
+        assert!(testee.try_add(&vote_tx_cost).is_err());
+    }
+
+    #[test]
+    fn test_vote_cost_reservation() {
+        let (mint_keypair, start_hash) = test_setup();
+        let (_tx, vote_tx_cost) = build_simple_vote_transaction(&mint_keypair, &start_hash);
+        let vote_cost = vote_tx_cost.sum();
+        let user_tx_cost = TransactionCost {
+            writable_accounts: vec![Pubkey::new_unique()],
+            bpf_execution_cost: vote_cost,
+            ..TransactionCost::default()
+        };
+
+        let mut testee = CostTracker::new(vote_cost * 4, vote_cost * 4, vote_cost * 2, None);
+        testee.set_vote_cost_reservation(vote_cost * 2);
+
+        // user transactions can not use the reserved vote capacity
+        assert!(testee.try_add(&user_tx_cost).is_ok());
+        assert!(testee.try_add(&user_tx_cost).is_ok());
+        assert_eq!(
+            Err(CostTrackerError::WouldExceedBlockMaxLimit),
+            testee.try_add(&user_tx_cost)
+        );
+
+        // votes still fit in a block saturated by user transactions
+        assert_eq!(vote_cost * 2, testee.remaining_vote_cus());
+        assert!(testee.try_add(&vote_tx_cost).is_ok());
+        assert!(testee.try_add(&vote_tx_cost).is_ok());
+        assert_eq!(0, testee.remaining_vote_cus());
+        assert!(testee.try_add(&vote_tx_cost).is_err());
+
+        // removed votes release their reservation back to votes only
+        testee.remove(&vote_tx_cost);
+        assert_eq!(
+            Err(CostTrackerError::WouldExceedBlockMaxLimit),
+            testee.try_add(&user_tx_cost)
+        );
+        assert!(testee.try_add(&vote_tx_cost).is_ok());
+
+        // the reservation is capped at the vote limit
+        let mut testee = CostTracker::new(vote_cost * 4, vote_cost * 4, vote_cost, None);
+        testee.set_vote_cost_reservation(vote_cost * 4);
+        for _ in 0..3 {
+            assert!(testee.try_add(&user_tx_cost).is_ok());
+        }
+        assert!(testee.try_add(&user_tx_cost).is_err());
+        assert!(testee.try_add(&vote_tx_cost).is_ok());
+    }
+
     #[test]
     fn test_cost_tracker_add_data() {
         let (mint_keypair, start_hash) = test_setup();
@@ -852,6 +1371,395 @@ mod tests {
         assert_eq!(1, cost_tracker.transaction_count);
     }
 
+    #[test]
+    fn test_cost_reservation() {
+        let acct1 = Pubkey::new_unique();
+        let acct2 = Pubkey::new_unique();
+        let acct3 = Pubkey::new_unique();
+        let cost = 100;
+        let mut testee = CostTracker::default();
+
+        // costs tracked outside of the reservation are unaffected by rollback
+        let tx_cost = TransactionCost {
+            writable_accounts: vec![acct1],
+            bpf_execution_cost: cost,
+            account_data_size: 10,
+            ..TransactionCost::default()
+        };
+        assert!(testee.try_add(&tx_cost).is_ok());
+
+        let program_id = Pubkey::new_unique();
+        let mut reservation = CostReservation::default();
+        for (writable_accounts, is_simple_vote) in [
+            (vec![acct1, acct2], false),
+            (vec![acct2, acct3], false),
+            (vec![acct3], true),
+        ] {
+            let tx_cost = TransactionCost {
+                writable_accounts,
+                bpf_execution_cost: cost,
+                account_data_size: 10,
+                is_simple_vote,
+                bpf_program_id: (!is_simple_vote).then_some(program_id),
+                ..TransactionCost::default()
+            };
+            assert!(testee.try_reserve(&mut reservation, &tx_cost).is_ok());
+        }
+        assert_eq!(3, reservation.transaction_count());
+        assert_eq!(cost * 3, reservation.block_cost());
+        assert_eq!(cost * 4, testee.block_cost());
+        assert_eq!(cost, testee.vote_cost);
+        assert_eq!(4, testee.transaction_count());
+        assert_eq!(40, testee.account_data_size);
+        assert_eq!(cost * 2, testee.program_execution_cost(&program_id));
+
+        testee.rollback_reservation(reservation);
+        assert_eq!(0, testee.program_execution_cost(&program_id));
+        assert_eq!(cost, testee.block_cost());
+        assert_eq!(0, testee.vote_cost);
+        assert_eq!(1, testee.transaction_count());
+        assert_eq!(10, testee.account_data_size);
+        assert_eq!(cost, testee.cost_by_writable_accounts[&acct1]);
+        assert_eq!(0, testee.cost_by_writable_accounts[&acct2]);
+        assert_eq!(0, testee.cost_by_writable_accounts[&acct3]);
+
+        // committed reservations keep their costs
+        let mut reservation = CostReservation::default();
+        assert!(testee.try_reserve(&mut reservation, &tx_cost).is_ok());
+        testee.commit_reservation(
+            reservation,
+            [(&tx_cost, ReservedTransactionOutcome::Committed)],
+        );
+        assert_eq!(cost * 2, testee.block_cost());
+        assert_eq!(2, testee.transaction_count());
+    }
+
+    #[test]
+    fn test_commit_reservation_outcomes() {
+        let program_id = Pubkey::new_unique();
+        let cost = 100;
+        let tx_costs: Vec<_> = (0..3)
+            .map(|_| TransactionCost {
+                writable_accounts: vec![Pubkey::new_unique()],
+                bpf_execution_cost: cost,
+                bpf_program_id: Some(program_id),
+                ..TransactionCost::default()
+            })
+            .collect();
+        let mut testee = CostTracker::default();
+
+        let mut reservation = CostReservation::default();
+        for tx_cost in &tx_costs {
+            assert!(testee.try_reserve(&mut reservation, tx_cost).is_ok());
+        }
+        testee.commit_reservation(
+            reservation,
+            tx_costs.iter().zip([
+                ReservedTransactionOutcome::NotCommitted,
+                ReservedTransactionOutcome::Committed,
+                ReservedTransactionOutcome::CommittedWithExecutionUnits(cost - 40),
+            ]),
+        );
+
+        // not committed transaction is removed, executed units are applied
+        assert_eq!(2, testee.transaction_count());
+        assert_eq!(cost * 2 - 40, testee.block_cost());
+        assert_eq!(
+            0,
+            testee.cost_by_writable_accounts[&tx_costs[0].writable_accounts[0]]
+        );
+        assert_eq!(
+            cost - 40,
+            testee.cost_by_writable_accounts[&tx_costs[2].writable_accounts[0]]
+        );
+        assert_eq!(
+            Some(&ExecutionCostEstimate {
+                transaction_count: 1,
+                estimated_units: cost,
+                actual_units: cost - 40,
+            }),
+            testee.execution_cost_estimate(&program_id)
+        );
+    }
+
+    #[test]
+    fn test_cost_reservation_would_exceed_limit() {
+        let acct1 = Pubkey::new_unique();
+        let cost = 100;
+        let mut testee = CostTracker::new(cost, cost, cost, None);
+        let tx_cost = TransactionCost {
+            writable_accounts: vec![acct1],
+            bpf_execution_cost: cost,
+            ..TransactionCost::default()
+        };
+
+        let mut reservation = CostReservation::default();
+        assert!(testee.try_reserve(&mut reservation, &tx_cost).is_ok());
+        assert_eq!(
+            Err(CostTrackerError::WouldExceedBlockMaxLimit),
+            testee.try_reserve(&mut reservation, &tx_cost)
+        );
+        // failed reservations are not recorded
+        assert_eq!(1, reservation.transaction_count());
+        testee.rollback_reservation(reservation);
+        assert_eq!(0, testee.block_cost());
+    }
+
+    #[test]
+    fn test_account_remaining_cus() {
+        let acct1 = Pubkey::new_unique();
+        let acct2 = Pubkey::new_unique();
+        let cost = 100;
+        let account_max = cost * 3;
+        let mut testee = CostTracker::new(account_max, account_max * 2, account_max * 2, None);
+        assert_eq!(account_max, testee.account_remaining_cus(&acct1));
+
+        let tx_cost = TransactionCost {
+            writable_accounts: vec![acct1],
+            bpf_execution_cost: cost,
+            ..TransactionCost::default()
+        };
+        assert!(testee.try_add(&tx_cost).is_ok());
+        assert!(testee.try_add(&tx_cost).is_ok());
+        assert_eq!(account_max - cost * 2, testee.account_remaining_cus(&acct1));
+        assert_eq!(account_max, testee.account_remaining_cus(&acct2));
+
+        // saturates at zero if actual cost pushed the account over the limit
+        testee.update_execution_cost(&tx_cost, cost * 3);
+        assert_eq!(0, testee.account_remaining_cus(&acct1));
+    }
+
+    #[test]
+    fn test_top_n_costly_accounts() {
+        let acct1 = Pubkey::new_unique();
+        let acct2 = Pubkey::new_unique();
+        let acct3 = Pubkey::new_unique();
+        let mut testee = CostTracker::default();
+        assert!(testee.top_n_costly_accounts(2).is_empty());
+
+        for (writable_accounts, cost) in [
+            (vec![acct1, acct2, acct3], 100),
+            (vec![acct2, acct3], 50),
+            (vec![acct3], 25),
+        ] {
+            let tx_cost = TransactionCost {
+                writable_accounts,
+                bpf_execution_cost: cost,
+                ..TransactionCost::default()
+            };
+            assert!(testee.try_add(&tx_cost).is_ok());
+        }
+
+        assert_eq!(
+            vec![(acct3, 175), (acct2, 150)],
+            testee.top_n_costly_accounts(2)
+        );
+        assert_eq!(vec![(acct3, 175)], testee.top_n_costly_accounts(1));
+        assert_eq!(
+            vec![(acct3, 175), (acct2, 150), (acct1, 100)],
+            testee.top_n_costly_accounts(3)
+        );
+        assert_eq!(
+            vec![(acct3, 175), (acct2, 150), (acct1, 100)],
+            testee.top_n_costly_accounts(10)
+        );
+        assert!(testee.top_n_costly_accounts(0).is_empty());
+    }
+
+    #[test]
+    fn test_update_execution_cost_estimates() {
+        let program_id = Pubkey::new_unique();
+        let cost = 100;
+        let tx_cost = TransactionCost {
+            writable_accounts: vec![Pubkey::new_unique()],
+            bpf_execution_cost: cost,
+            bpf_program_id: Some(program_id),
+            ..TransactionCost::default()
+        };
+
+        let mut cost_tracker = CostTracker::default();
+        assert!(cost_tracker.try_add(&tx_cost).is_ok());
+        assert!(cost_tracker.try_add(&tx_cost).is_ok());
+        assert!(cost_tracker.execution_cost_estimate(&program_id).is_none());
+
+        // over-estimated units are released from the block
+        cost_tracker.update_execution_cost(&tx_cost, cost - 30);
+        cost_tracker.update_execution_cost(&tx_cost, cost - 10);
+        assert_eq!(cost * 2 - 40, cost_tracker.block_cost());
+        assert_eq!(
+            Some(&ExecutionCostEstimate {
+                transaction_count: 2,
+                estimated_units: cost * 2,
+                actual_units: cost * 2 - 40,
+            }),
+            cost_tracker.execution_cost_estimate(&program_id)
+        );
+        assert_eq!(
+            40,
+            cost_tracker
+                .execution_cost_estimate(&program_id)
+                .unwrap()
+                .estimate_error()
+        );
+
+        // transactions without a bpf program are not attributed
+        let builtin_tx_cost = TransactionCost {
+            writable_accounts: vec![Pubkey::new_unique()],
+            builtins_execution_cost: cost,
+            ..TransactionCost::default()
+        };
+        assert!(cost_tracker.try_add(&builtin_tx_cost).is_ok());
+        cost_tracker.update_execution_cost(&builtin_tx_cost, 0);
+        assert_eq!(1, cost_tracker.execution_cost_estimates.len());
+    }
+
+    #[test]
+    fn test_update_execution_cost_estimates_bounded() {
+        let cost = 100;
+        let mut cost_tracker = CostTracker::default();
+        let tx_costs: Vec<_> = (0..=MAX_TRACKED_EXECUTION_COST_ESTIMATES)
+            .map(|_| TransactionCost {
+                writable_accounts: vec![Pubkey::new_unique()],
+                bpf_execution_cost: cost,
+                bpf_program_id: Some(Pubkey::new_unique()),
+                ..TransactionCost::default()
+            })
+            .collect();
+        for tx_cost in &tx_costs {
+            assert!(cost_tracker.try_add(tx_cost).is_ok());
+            cost_tracker.update_execution_cost(tx_cost, cost - 1);
+        }
+
+        // programs past the bound are not tracked, but their costs are still adjusted
+        assert_eq!(
+            MAX_TRACKED_EXECUTION_COST_ESTIMATES,
+            cost_tracker.execution_cost_estimates.len()
+        );
+        let untracked_tx_cost = tx_costs.last().unwrap();
+        assert!(cost_tracker
+            .execution_cost_estimate(&untracked_tx_cost.bpf_program_id.unwrap())
+            .is_none());
+        assert_eq!(
+            (cost - 1) * tx_costs.len() as u64,
+            cost_tracker.block_cost()
+        );
+
+        // already tracked programs keep accumulating
+        let tracked_tx_cost = &tx_costs[0];
+        assert!(cost_tracker.try_add(tracked_tx_cost).is_ok());
+        cost_tracker.update_execution_cost(tracked_tx_cost, cost - 1);
+        assert_eq!(
+            2,
+            cost_tracker
+                .execution_cost_estimate(&tracked_tx_cost.bpf_program_id.unwrap())
+                .unwrap()
+                .transaction_count
+        );
+    }
+
+    #[test]
+    fn test_program_execution_cost() {
+        let program1 = Pubkey::new_unique();
+        let program2 = Pubkey::new_unique();
+        let builtin_program = Pubkey::new_unique();
+        let vote_program = Pubkey::new_unique();
+        let cost = 100;
+        let signature_cost = 10;
+        let tx_cost_1 = TransactionCost {
+            writable_accounts: vec![Pubkey::new_unique()],
+            signature_cost,
+            bpf_execution_cost: cost,
+            bpf_program_id: Some(program1),
+            ..TransactionCost::default()
+        };
+        let tx_cost_2 = TransactionCost {
+            writable_accounts: vec![Pubkey::new_unique()],
+            signature_cost,
+            builtins_execution_cost: cost,
+            bpf_execution_cost: cost * 2,
+            bpf_program_id: Some(program2),
+            builtin_program_costs: vec![(builtin_program, cost)],
+            ..TransactionCost::default()
+        };
+        let vote_tx_cost = TransactionCost {
+            writable_accounts: vec![Pubkey::new_unique()],
+            signature_cost,
+            builtins_execution_cost: cost,
+            builtin_program_costs: vec![(vote_program, cost)],
+            is_simple_vote: true,
+            ..TransactionCost::default()
+        };
+
+        let mut testee = CostTracker::default();
+        assert!(testee.try_add(&tx_cost_1).is_ok());
+        assert!(testee.try_add(&tx_cost_1).is_ok());
+        assert!(testee.try_add(&tx_cost_2).is_ok());
+        assert!(testee.try_add(&vote_tx_cost).is_ok());
+        assert_eq!(cost * 2, testee.program_execution_cost(&program1));
+        assert_eq!(cost * 2, testee.program_execution_cost(&program2));
+        assert_eq!(cost, testee.program_execution_cost(&builtin_program));
+        assert_eq!(cost, testee.program_execution_cost(&vote_program));
+
+        // fixed costs are tracked separately from execution costs
+        assert_eq!(signature_cost * 4, testee.fixed_cost());
+        assert_eq!(cost * 6 + signature_cost * 4, testee.block_cost());
+
+        // adjusted to actual execution units
+        testee.update_execution_cost(&tx_cost_2, cost * 3);
+        assert_eq!(cost * 3, testee.program_execution_cost(&program2));
+        assert_eq!(cost, testee.program_execution_cost(&builtin_program));
+        testee.update_execution_cost(&vote_tx_cost, 0);
+        assert_eq!(cost, testee.program_execution_cost(&vote_program));
+        assert_eq!(
+            vec![
+                (program2, cost * 3),
+                (program1, cost * 2),
+                (builtin_program.min(vote_program), cost),
+                (builtin_program.max(vote_program), cost),
+            ],
+            testee.costliest_programs()
+        );
+
+        // removed with the transaction
+        testee.remove(&tx_cost_1);
+        testee.remove(&tx_cost_1);
+        testee.remove(&vote_tx_cost);
+        assert_eq!(0, testee.program_execution_cost(&program1));
+        assert_eq!(0, testee.program_execution_cost(&vote_program));
+        assert_eq!(signature_cost, testee.fixed_cost());
+        assert_eq!(
+            vec![(program2, cost * 3), (builtin_program, cost)],
+            testee.costliest_programs()
+        );
+    }
+
+    #[test]
+    fn test_worst_execution_cost_estimates() {
+        let mut cost_tracker = CostTracker::default();
+        let num_programs = MAX_REPORTED_EXECUTION_COST_ESTIMATES + 2;
+        let program_ids: Vec<_> = (0..num_programs).map(|_| Pubkey::new_unique()).collect();
+        for (error, program_id) in program_ids.iter().enumerate() {
+            let tx_cost = TransactionCost {
+                bpf_execution_cost: 1_000,
+                bpf_program_id: Some(*program_id),
+                ..TransactionCost::default()
+            };
+            // alternate over- and under-estimates of increasing size
+            let actual_units = if error % 2 == 0 {
+                1_000 - error as u64
+            } else {
+                1_000 + error as u64
+            };
+            cost_tracker.update_execution_cost(&tx_cost, actual_units);
+        }
+
+        let worst = cost_tracker.worst_execution_cost_estimates();
+        assert_eq!(MAX_REPORTED_EXECUTION_COST_ESTIMATES, worst.len());
+        for (index, (program_id, _)) in worst.into_iter().enumerate() {
+            assert_eq!(&program_ids[num_programs - 1 - index], program_id);
+        }
+    }
+
     #[test]
     fn test_remove_transaction_cost() {
         let mut cost_tracker = CostTracker::default();
@@ -2,6 +2,7 @@
 #![allow(clippy::integer_arithmetic)]
 
 pub mod block_cost_limits;
+pub mod builtin_costs;
 pub mod cost_model;
 pub mod cost_tracker;
 pub mod transaction_cost;
@@ -14,6 +14,11 @@ pub struct TransactionCost {
     pub loaded_accounts_data_size_cost: u64,
     pub account_data_size: u64,
     pub is_simple_vote: bool,
+    /// Program of the first non-builtin instruction, used to attribute errors
+    /// in the estimated execution cost.
+    pub bpf_program_id: Option<Pubkey>,
+    /// Execution cost of each builtin instruction, by program.
+    pub builtin_program_costs: Vec<(Pubkey, u64)>,
 }
 
 impl Default for TransactionCost {
@@ -28,6 +33,8 @@ impl Default for TransactionCost {
             loaded_accounts_data_size_cost: 0u64,
             account_data_size: 0u64,
             is_simple_vote: false,
+            bpf_program_id: None,
+            builtin_program_costs: Vec::new(),
         }
     }
 }
@@ -47,6 +54,8 @@ impl PartialEq for TransactionCost {
             && self.loaded_accounts_data_size_cost == other.loaded_accounts_data_size_cost
             && self.account_data_size == other.account_data_size
             && self.is_simple_vote == other.is_simple_vote
+            && self.bpf_program_id == other.bpf_program_id
+            && self.builtin_program_costs == other.builtin_program_costs
             && to_hash_set(&self.writable_accounts) == to_hash_set(&other.writable_accounts)
     }
 }
@@ -67,11 +76,42 @@ impl TransactionCost {
     }
 
     pub fn sum(&self) -> u64 {
+        self.fixed_cost().saturating_add(self.execution_cost())
+    }
+
+    /// Cost of signatures, write locks, instruction data and loaded accounts
+    /// data, which does not depend on how the transaction executes.
+    pub fn fixed_cost(&self) -> u64 {
         self.signature_cost
             .saturating_add(self.write_lock_cost)
             .saturating_add(self.data_bytes_cost)
-            .saturating_add(self.builtins_execution_cost)
-            .saturating_add(self.bpf_execution_cost)
             .saturating_add(self.loaded_accounts_data_size_cost)
     }
+
+    /// Estimated cost of executing the builtin and bpf instructions.
+    pub fn execution_cost(&self) -> u64 {
+        self.builtins_execution_cost
+            .saturating_add(self.bpf_execution_cost)
+    }
+
+    /// Estimated execution cost by program. Builtin instructions are charged
+    /// to their own programs; the bpf execution cost is only estimated for
+    /// the whole transaction, so it is charged to `bpf_program_id`.
+    pub fn program_execution_costs(&self) -> impl Iterator<Item = (Pubkey, u64)> + '_ {
+        self.builtin_program_costs.iter().copied().chain(
+            self.bpf_program_id
+                .map(|program_id| (program_id, self.bpf_execution_cost)),
+        )
+    }
+
+    /// Program charged with adjustments to the estimated execution cost: the
+    /// program of the first non-builtin instruction, or of the first builtin
+    /// instruction if the transaction has none.
+    pub fn execution_cost_program_id(&self) -> Option<Pubkey> {
+        self.bpf_program_id.or_else(|| {
+            self.builtin_program_costs
+                .first()
+                .map(|(program_id, _)| *program_id)
+        })
+    }
 }
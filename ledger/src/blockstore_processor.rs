@@ -344,7 +344,11 @@ fn execute_batches(
     let tx_costs = sanitized_txs
         .iter()
         .map(|tx| {
-            let tx_cost = CostModel::calculate_cost(tx, &bank.feature_set);
+            let tx_cost = CostModel::calculate_cost_with_builtin_cost_table(
+                tx,
+                &bank.feature_set,
+                bank.builtin_cost_table(),
+            );
             let cost = tx_cost.sum();
             minimal_tx_cost = std::cmp::min(minimal_tx_cost, cost);
             total_cost = total_cost.saturating_add(cost);
@@ -96,7 +96,10 @@ use {
         ThreadPool, ThreadPoolBuilder,
     },
     solana_bpf_loader_program::syscalls::create_program_runtime_environment,
-    solana_cost_model::{cost_model::CostModel, cost_tracker::CostTracker},
+    solana_cost_model::{
+        block_cost_limits::BlockCostLimits, builtin_costs::BuiltinCostTable, cost_model::CostModel,
+        cost_tracker::CostTracker,
+    },
     solana_measure::{measure, measure::Measure, measure_us},
     solana_perf::perf_libs,
     solana_program_runtime::{
@@ -532,6 +535,8 @@ impl PartialEq for Bank {
             freeze_started: _,
             vote_only_bank: _,
             cost_tracker: _,
+            block_cost_limits: _,
+            builtin_cost_table: _,
             sysvar_cache: _,
             accounts_data_size_initial: _,
             accounts_data_size_delta_on_chain: _,
@@ -791,6 +796,15 @@ pub struct Bank {
 
     cost_tracker: RwLock<CostTracker>,
 
+    /// Cost limits of the block, enforced by `cost_tracker`. Inherited from
+    /// the parent bank.
+    block_cost_limits: BlockCostLimits,
+
+    /// Builtin instruction costs used to calculate the cost of transactions
+    /// for this bank. Built when the root bank is created at startup and
+    /// shared by all of its descendants.
+    builtin_cost_table: Arc<BuiltinCostTable>,
+
     sysvar_cache: RwLock<SysvarCache>,
 
     /// The initial accounts data size at the start of this Bank, before processing any transactions/etc
@@ -1076,6 +1090,8 @@ impl Bank {
             freeze_started: AtomicBool::default(),
             vote_only_bank: false,
             cost_tracker: RwLock::<CostTracker>::default(),
+            block_cost_limits: BlockCostLimits::default(),
+            builtin_cost_table: Arc::<BuiltinCostTable>::default(),
             sysvar_cache: RwLock::<SysvarCache>::default(),
             accounts_data_size_initial: 0,
             accounts_data_size_delta_on_chain: AtomicI64::new(0),
@@ -1419,7 +1435,8 @@ impl Bank {
                     .map(|drop_callback| drop_callback.clone_box()),
             )),
             freeze_started: AtomicBool::new(false),
-            cost_tracker: RwLock::new(CostTracker::new_with_account_data_size_limit(
+            cost_tracker: RwLock::new(CostTracker::new_with_block_cost_limits(
+                parent.block_cost_limits,
                 feature_set
                     .is_active(&feature_set::cap_accounts_data_len::id())
                     .then(|| {
@@ -1428,6 +1445,8 @@ impl Bank {
                             .saturating_sub(accounts_data_size_initial)
                     }),
             )),
+            block_cost_limits: parent.block_cost_limits,
+            builtin_cost_table: Arc::clone(&parent.builtin_cost_table),
             sysvar_cache: RwLock::new(SysvarCache::default()),
             accounts_data_size_initial,
             accounts_data_size_delta_on_chain: AtomicI64::new(0),
@@ -1878,6 +1897,8 @@ impl Bank {
             freeze_started: AtomicBool::new(fields.hash != Hash::default()),
             vote_only_bank: false,
             cost_tracker: RwLock::new(CostTracker::default()),
+            block_cost_limits: BlockCostLimits::default(),
+            builtin_cost_table: Arc::<BuiltinCostTable>::default(),
             sysvar_cache: RwLock::new(SysvarCache::default()),
             accounts_data_size_initial,
             accounts_data_size_delta_on_chain: AtomicI64::new(0),
@@ -6973,10 +6994,13 @@ impl Bank {
             .feature_set
             .is_active(&feature_set::cap_accounts_data_len::id())
         {
-            self.cost_tracker = RwLock::new(CostTracker::new_with_account_data_size_limit(Some(
-                self.accounts_data_size_limit()
-                    .saturating_sub(self.accounts_data_size_initial),
-            )));
+            self.cost_tracker = RwLock::new(CostTracker::new_with_block_cost_limits(
+                self.block_cost_limits,
+                Some(
+                    self.accounts_data_size_limit()
+                        .saturating_sub(self.accounts_data_size_initial),
+                ),
+            ));
         }
     }
 
@@ -8104,6 +8128,27 @@ impl Bank {
         self.cost_tracker.write()
     }
 
+    /// Returns the builtin instruction costs to calculate transaction costs
+    /// with for this bank.
+    pub fn builtin_cost_table(&self) -> &BuiltinCostTable {
+        &self.builtin_cost_table
+    }
+
+    /// Returns the cost limits the bank's cost tracker enforces on the block.
+    pub fn block_cost_limits(&self) -> BlockCostLimits {
+        self.block_cost_limits
+    }
+
+    /// Sets the cost limits of the block, which are enforced by the bank's
+    /// cost tracker and inherited by child banks.
+    pub fn set_block_cost_limits(&mut self, block_cost_limits: BlockCostLimits) {
+        self.block_cost_limits = block_cost_limits;
+        self.cost_tracker
+            .get_mut()
+            .unwrap()
+            .set_block_cost_limits(block_cost_limits);
+    }
+
     // Check if the wallclock time from bank creation to now has exceeded the allotted
     // time for transaction processing
     pub fn should_bank_still_be_processing_txs(
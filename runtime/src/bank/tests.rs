@@ -13685,3 +13685,36 @@ fn test_calculate_stake_vote_rewards() {
         expected_reward_info,
     );
 }
+
+#[test]
+fn test_block_cost_limits() {
+    let (genesis_config, _mint_keypair) = create_genesis_config(1);
+    let mut parent = Bank::new_for_tests(&genesis_config);
+    assert_eq!(parent.block_cost_limits(), BlockCostLimits::default());
+
+    let block_cost_limits = BlockCostLimits::default().divided_by(2);
+    parent.set_block_cost_limits(block_cost_limits);
+    assert_eq!(parent.block_cost_limits(), block_cost_limits);
+    assert_eq!(
+        *parent.read_cost_tracker().unwrap().block_cost_limits(),
+        block_cost_limits
+    );
+
+    let bank = Bank::new_from_parent(&Arc::new(parent), &Pubkey::default(), 1);
+    assert_eq!(bank.block_cost_limits(), block_cost_limits);
+    assert_eq!(
+        *bank.read_cost_tracker().unwrap().block_cost_limits(),
+        block_cost_limits
+    );
+}
+
+#[test]
+fn test_builtin_cost_table_inherited() {
+    let (genesis_config, _mint_keypair) = create_genesis_config(1);
+    let parent = Arc::new(Bank::new_for_tests(&genesis_config));
+    let bank = Bank::new_from_parent(&parent, &Pubkey::default(), 1);
+    assert!(Arc::ptr_eq(
+        &parent.builtin_cost_table,
+        &bank.builtin_cost_table
+    ));
+}
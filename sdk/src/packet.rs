@@ -33,6 +33,9 @@ bitflags! {
         /// the packet is built.
         /// This field can be removed when the above feature gate is adopted by mainnet-beta.
         const ROUND_COMPUTE_UNIT_PRICE = 0b0010_0000;
+        /// to be set on packets that must never be forwarded to other nodes,
+        /// e.g. locally generated or admin-injected transactions.
+        const NON_FORWARDABLE = 0b0100_0000;
     }
 }
 
@@ -226,6 +229,11 @@ impl Meta {
         );
     }
 
+    #[inline]
+    pub fn set_non_forwardable(&mut self, local_only: bool) {
+        self.flags.set(PacketFlags::NON_FORWARDABLE, local_only);
+    }
+
     #[inline]
     pub fn forwarded(&self) -> bool {
         self.flags.contains(PacketFlags::FORWARDED)
@@ -250,6 +258,11 @@ impl Meta {
     pub fn round_compute_unit_price(&self) -> bool {
         self.flags.contains(PacketFlags::ROUND_COMPUTE_UNIT_PRICE)
     }
+
+    #[inline]
+    pub fn non_forwardable(&self) -> bool {
+        self.flags.contains(PacketFlags::NON_FORWARDABLE)
+    }
 }
 
 impl Default for Meta {